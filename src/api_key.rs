@@ -0,0 +1,246 @@
+//! 服務之間呼叫用的 API key
+//!
+//! - 金鑰格式為 `pk_<prefix>_<secret>`，建立時只回傳一次，資料庫只保存 SHA-256 雜湊與 prefix
+//! - 金鑰本身是 32 bytes 的隨機值，不怕暴力破解，因此不需要 argon2 這類刻意變慢的雜湊，
+//!   以雜湊值直接查詢即可
+//! - 權限以 `resource:access` 表示，例如 `posts:write`；write 包含 read
+
+use axum::http::{header, HeaderMap, HeaderName};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// 所有 API key 的開頭，用來與 JWT 區分
+pub const KEY_PREFIX: &str = "pk_";
+/// 以 header 傳送 API key 時使用的名稱
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// 新產生的金鑰
+pub struct GeneratedKey {
+    /// 完整金鑰，只在建立時回傳給使用者
+    pub key: String,
+    /// 公開的識別碼，方便在列表中辨認是哪一把金鑰
+    pub prefix: String,
+    pub hash: String,
+}
+
+/// 產生新的 API key
+pub fn generate() -> GeneratedKey {
+    let mut prefix = [0u8; 4];
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut prefix);
+    OsRng.fill_bytes(&mut secret);
+    let prefix = hex::encode(prefix);
+    let key = format!("{}{}_{}", KEY_PREFIX, prefix, hex::encode(secret));
+    GeneratedKey {
+        hash: hash_key(&key),
+        key,
+        prefix,
+    }
+}
+
+/// 計算金鑰的 SHA-256 (16 進位)
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// 從 `X-API-Key` 或 `Authorization: Bearer pk_...` 取出 API key，兩者都有時以 `X-API-Key` 為準
+/// 各自不是 API key 格式時略過：Bearer 的值交給 JWT 驗證，格式錯誤的 `X-API-Key` 由呼叫端回傳 401
+pub fn from_headers(headers: &HeaderMap) -> Option<&str> {
+    let is_key = |key: &&str| key.starts_with(KEY_PREFIX);
+    let header = headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(is_key);
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(is_key);
+    header.or(bearer)
+}
+
+/// 可授權的資源
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Posts,
+    Comments,
+    Tags,
+    Users,
+}
+
+/// 讀取 (GET / HEAD) 或寫入 (其他方法)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+const RESOURCES: [(Resource, &str); 4] = [
+    (Resource::Posts, "posts"),
+    (Resource::Comments, "comments"),
+    (Resource::Tags, "tags"),
+    (Resource::Users, "users"),
+];
+
+/// 單一權限，例如 `posts:read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scope {
+    pub resource: Resource,
+    pub access: Access,
+}
+
+impl Scope {
+    /// 解析 `resource:access` 格式的字串
+    pub fn parse(value: &str) -> Result<Scope, String> {
+        let invalid = || format!("Invalid scope `{}`, expected e.g. posts:read or posts:write", value);
+        let (resource, access) = value.split_once(':').ok_or_else(invalid)?;
+        let resource = RESOURCES
+            .iter()
+            .find(|(_, name)| *name == resource)
+            .map(|(r, _)| *r)
+            .ok_or_else(invalid)?;
+        let access = match access {
+            "read" => Access::Read,
+            "write" => Access::Write,
+            _ => return Err(invalid()),
+        };
+        Ok(Scope { resource, access })
+    }
+
+    // 在 Scopes 位元組中的位置
+    fn bit(self) -> u8 {
+        let index = RESOURCES.iter().position(|(r, _)| *r == self.resource).unwrap_or(0);
+        1 << (index * 2 + usize::from(self.access == Access::Write))
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resource = RESOURCES.iter().find(|(r, _)| *r == self.resource).map_or("", |(_, n)| *n);
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+        };
+        write!(f, "{}:{}", resource, access)
+    }
+}
+
+/// 一把金鑰擁有的所有權限
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Scopes(u8);
+
+impl Scopes {
+    /// 解析以空白分隔的權限清單 (資料庫中的儲存格式)
+    pub fn parse(value: &str) -> Result<Scopes, String> {
+        value.split_whitespace().try_fold(Scopes::default(), |scopes, s| {
+            Ok(Scopes(scopes.0 | Scope::parse(s)?.bit()))
+        })
+    }
+
+    /// 是否擁有此權限，write 包含 read
+    pub fn allows(self, scope: Scope) -> bool {
+        let write = Scope {
+            access: Access::Write,
+            ..scope
+        };
+        self.0 & (scope.bit() | write.bit()) != 0
+    }
+
+    /// 依固定順序列出，作為儲存與回應的格式
+    pub fn to_vec(self) -> Vec<String> {
+        RESOURCES
+            .iter()
+            .flat_map(|(resource, _)| {
+                [Access::Read, Access::Write].map(|access| Scope {
+                    resource: *resource,
+                    access,
+                })
+            })
+            .filter(|scope| self.0 & scope.bit() != 0)
+            .map(|scope| scope.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(value: &str) -> Scope {
+        Scope::parse(value).unwrap()
+    }
+
+    #[test]
+    fn scope_round_trips_through_display() {
+        for value in ["posts:read", "posts:write", "comments:read", "tags:write", "users:read"] {
+            assert_eq!(scope(value).to_string(), value);
+        }
+    }
+
+    #[test]
+    fn scope_rejects_unknown_resource_or_access() {
+        for value in ["posts", "posts:", "post:read", "posts:admin", ":read", "posts:read:write"] {
+            assert!(Scope::parse(value).is_err(), "{} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn each_scope_has_its_own_bit() {
+        let all = Scopes::parse(
+            "posts:read posts:write comments:read comments:write tags:read tags:write users:read users:write",
+        )
+        .unwrap();
+        assert_eq!(all.0, u8::MAX);
+        assert_eq!(Scopes::parse("").unwrap(), Scopes::default());
+        assert!(Scopes::parse("posts:read bogus").is_err());
+    }
+
+    #[test]
+    fn write_implies_read_but_not_the_other_way() {
+        let scopes = Scopes::parse("posts:write comments:read").unwrap();
+        assert!(scopes.allows(scope("posts:read")));
+        assert!(scopes.allows(scope("posts:write")));
+        assert!(scopes.allows(scope("comments:read")));
+        assert!(!scopes.allows(scope("comments:write")));
+        assert!(!scopes.allows(scope("tags:read")));
+        assert!(!scopes.allows(scope("users:write")));
+    }
+
+    #[test]
+    fn to_vec_is_ordered_and_deduplicated() {
+        let scopes = Scopes::parse("users:read posts:write users:read posts:read").unwrap();
+        assert_eq!(scopes.to_vec(), ["posts:read", "posts:write", "users:read"]);
+        assert_eq!(Scopes::parse(&scopes.to_vec().join(" ")).unwrap(), scopes);
+    }
+
+    #[test]
+    fn api_key_is_taken_from_header_or_bearer() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer pk_abcd_1234".parse().unwrap());
+        assert_eq!(from_headers(&headers), Some("pk_abcd_1234"));
+        headers.insert(API_KEY_HEADER, " pk_efgh_5678 ".parse().unwrap());
+        assert_eq!(from_headers(&headers), Some("pk_efgh_5678"));
+
+        let mut jwt = HeaderMap::new();
+        jwt.insert(header::AUTHORIZATION, "Bearer eyJhbGciOi".parse().unwrap());
+        assert_eq!(from_headers(&jwt), None);
+
+        // 格式錯誤的 X-API-Key 不會蓋掉 Bearer 中有效格式的金鑰
+        let mut malformed = HeaderMap::new();
+        malformed.insert(API_KEY_HEADER, "not-a-key".parse().unwrap());
+        assert_eq!(from_headers(&malformed), None);
+        malformed.insert(header::AUTHORIZATION, "Bearer pk_abcd_1234".parse().unwrap());
+        assert_eq!(from_headers(&malformed), Some("pk_abcd_1234"));
+    }
+
+    #[test]
+    fn generated_key_matches_its_hash_and_prefix() {
+        let generated = generate();
+        assert!(generated.key.starts_with(&format!("{}{}_", KEY_PREFIX, generated.prefix)));
+        assert_eq!(hash_key(&generated.key), generated.hash);
+        assert_ne!(generate().key, generated.key);
+    }
+}
@@ -0,0 +1,44 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 使用者建立的 API key，代表該使用者呼叫 API，但只能使用 `scopes` 內的權限
+/// 金鑰本身不保存，只保存 SHA-256 雜湊 (見 src/api_key.rs)
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// 金鑰中公開的識別碼，例: pk_<prefix>_...
+    pub prefix: String,
+    #[serde(skip)]
+    #[sea_orm(unique)]
+    pub key_hash: String,
+    /// 以空白分隔的權限，例: "posts:read comments:write"
+    pub scopes: String,
+    /// 為 NULL 代表永不過期
+    pub expires_at: Option<DateTimeUtc>,
+    pub last_used_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+}
+
+/// 使用者被刪除時，其 API key 一併刪除
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user_entity::Entity",
+        from = "Column::UserId",
+        to = "super::user_entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<super::user_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
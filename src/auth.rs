@@ -0,0 +1,146 @@
+//! 帳號密碼與 JWT 的共用工具
+//!
+//! - 密碼以 argon2id 雜湊，儲存 PHC 格式字串 (內含 salt 與參數)，不保存明文
+//! - access token 有效 15 分鐘，用於呼叫 API；refresh token 有效 7 天，只能用來換發新的 token
+//! - 兩種 token 都以 HS256 簽章，並帶有隨機的 `jti`，登出或換發時以 `jti` 記錄撤銷
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::http::{header, HeaderMap};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// access token 的有效時間
+pub const ACCESS_TOKEN_TTL: Duration = Duration::from_secs(15 * 60);
+/// refresh token 的有效時間
+pub const REFRESH_TOKEN_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// 密碼最短長度 (字元數)
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// token 的用途，避免 refresh token 被拿來呼叫 API (或反過來)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+}
+
+/// JWT 的內容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// 使用者 id
+    pub sub: i64,
+    pub typ: TokenType,
+    /// token 的唯一識別碼，撤銷時使用
+    pub jti: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// 登入與換發 token 的回應
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    /// access token 的有效秒數
+    pub expires_in: u64,
+}
+
+/// 檢查密碼是否符合最低要求
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+/// 以 argon2id 與隨機 salt 雜湊密碼
+/// 雜湊刻意設計得很耗 CPU，呼叫端應在 blocking 執行緒上執行
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+/// 驗證密碼是否與儲存的雜湊相符，雜湊格式錯誤也視為不符
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+/// 取出 `Authorization: Bearer <token>` 中的 token
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+// 產生 n bytes 的隨機值並轉為 16 進位字串
+fn random_hex(n: usize) -> String {
+    let mut bytes = vec![0u8; n];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// 目前時間 (Unix 秒)，與 token 的 `exp` 比較用
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after 1970")
+        .as_secs() as i64
+}
+
+/// 簽章與驗證 JWT 用的金鑰
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    /// 隨機產生的金鑰，伺服器重新啟動後先前發出的 token 全部失效
+    pub fn random() -> Self {
+        Self::from_secret(random_hex(32).as_bytes())
+    }
+
+    /// 簽發指定用途的 token，回傳 token 字串與其內容
+    pub fn issue(&self, user_id: i64, typ: TokenType) -> jsonwebtoken::errors::Result<(String, Claims)> {
+        let ttl = match typ {
+            TokenType::Access => ACCESS_TOKEN_TTL,
+            TokenType::Refresh => REFRESH_TOKEN_TTL,
+        };
+        let iat = unix_now();
+        let claims = Claims {
+            sub: user_id,
+            typ,
+            jti: random_hex(16),
+            iat,
+            exp: iat + ttl.as_secs() as i64,
+        };
+        let token = encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)?;
+        Ok((token, claims))
+    }
+
+    /// 驗證簽章、有效期限與用途
+    pub fn verify(&self, token: &str, typ: TokenType) -> jsonwebtoken::errors::Result<Claims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        let claims = decode::<Claims>(token, &self.decoding, &validation)?.claims;
+        if claims.typ != typ {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+        }
+        Ok(claims)
+    }
+}
@@ -33,7 +33,7 @@ fn main() {
     println!("=== Rust 計算機範例 ===");
     
     // 這裡我們直接模擬一些操作，實際應用通常會結合解析輸入
-    let ops = vec![
+    let ops = [
         Operation::Add(10.0, 5.0),
         Operation::Subtract(20.0, 7.5),
         Operation::Multiply(3.0, 4.0),
@@ -1,16 +1,42 @@
 use axum::{
-    extract::Json,
+    extract::{Json, State},
     http::StatusCode,
+    middleware,
     response::IntoResponse,
-    routing::get,
-    Router,
 };
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+#[path = "../rate_limit.rs"]
+mod rate_limit;
+use rate_limit::RateLimiter;
+
+#[path = "../telemetry.rs"]
+mod telemetry;
+
+#[path = "../metrics.rs"]
+mod metrics;
+
+#[path = "../health.rs"]
+mod health;
+
+#[path = "../shutdown.rs"]
+mod shutdown;
+use shutdown::Shutdown;
+
+#[path = "../http_layers.rs"]
+mod http_layers;
+use http_layers::HttpLayers;
+
+#[path = "../openapi.rs"]
+mod openapi;
 
 /// 使用者資料模型
 /// Derive 巨集自動實作序列化與反序列化
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 struct User {
     id: u64,
     username: String,
@@ -19,42 +45,101 @@ struct User {
 
 /// 建立使用者的請求 Payload
 /// 不需要 id，因為由伺服器生成
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct CreateUserPayload {
     username: String,
     email: String,
 }
 
+/// OpenAPI 文件的標題與說明，各路由的說明寫在 handler 的 `#[utoipa::path]`
+#[derive(OpenApi)]
+#[openapi(info(title = "Rust Demo API", description = "範例 04: 簡易 RESTful API，資料為記憶體中的範例"))]
+struct ApiDoc;
+
 /// 範例 04: 簡易 RESTful API
 /// 使用 Axum 框架
 #[tokio::main]
 async fn main() {
-    // 建立路由
-    let app = Router::new()
-        .route("/", get(root_handler))
-        .route("/users", get(doc_get_users).post(doc_create_user));
+    // 日誌格式與層級見 src/telemetry.rs (LOG_FORMAT / RUST_LOG)
+    telemetry::init().expect("invalid logging configuration");
+
+    // 限流設定見 src/rate_limit.rs (RATE_LIMIT_READ / RATE_LIMIT_WRITE / RATE_LIMIT_DB)
+    let limiter = Arc::new(RateLimiter::from_env().expect("invalid rate limit configuration"));
+    // 關閉流程見 src/shutdown.rs (SHUTDOWN_DELAY / SHUTDOWN_TIMEOUT)
+    let shutdown = Shutdown::install().expect("invalid shutdown configuration");
+    // CORS、壓縮、請求大小限制、逾時與安全性 header 見 src/http_layers.rs
+    let http_layers = HttpLayers::from_env().expect("invalid HTTP configuration");
+
+    // 建立路由，同時產生 OpenAPI 文件 (GET /openapi.json、GET /docs)
+    let app = OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(root_handler))
+        .routes(routes!(doc_get_users, doc_create_user))
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit::limit))
+        // 加在限流之後，Prometheus 抓取指標與健康檢查不受限流影響
+        .routes(routes!(get_metrics))
+        .routes(routes!(health::healthz))
+        .routes(routes!(readyz));
+    let app = openapi::finish(app).with_state(shutdown.clone());
+    let app = http_layers
+        .apply(app)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(telemetry::trace_request));
 
     // 定義監聽位址
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("API 伺服器啟動於 http://{}", addr);
+    tracing::info!("API 伺服器啟動於 http://{}", addr);
 
     // 啟動伺服器
     // axum 0.8+ 使用 serve 方式略有不同，這裡假設使用最新版或相容舊版寫法
     // 根據 cargo add 結果，我們應該檢查 axum 版本。
     // 如果是 0.7.x:
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // 帶上連線資訊，限流才能以來源 IP 區分 client
+    // 收到 SIGINT / SIGTERM 後等處理中的請求完成才結束
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone().stop_accepting());
+    shutdown.drain(server).await.unwrap();
+    tracing::info!("伺服器已停止");
 }
 
 /// 根路徑處理器
+#[utoipa::path(get, path = "/", summary = "檢查伺服器是否運作", responses((status = 200, description = "Hello, Rust API!")))]
 async fn root_handler() -> &'static str {
     "Hello, Rust API!"
 }
 
+/// GET /metrics 處理器 (Prometheus 格式)
+#[utoipa::path(get, path = "/metrics", tag = "ops", summary = "Prometheus 指標", responses((status = 200, description = "Prometheus text format")))]
+async fn get_metrics() -> impl IntoResponse {
+    metrics::render(&[])
+}
+
+/// GET /readyz 處理器
+/// 資料都在記憶體中，沒有需要檢查的依賴，只有正在關閉時回傳 503
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "ops",
+    summary = "是否可以接受請求",
+    responses(
+        (status = 200, description = "可以接受請求", body = health::Readiness),
+        (status = 503, description = "正在關閉", body = health::Readiness),
+    ),
+)]
+async fn readyz(State(shutdown): State<Shutdown>) -> impl IntoResponse {
+    health::readiness(shutdown.is_draining(), Vec::new())
+}
+
 /// GET /users 處理器 (回傳範例資料)
+#[utoipa::path(get, path = "/users", tag = "users", summary = "取得使用者列表", responses((status = 200, description = "所有使用者", body = Vec<User>)))]
 async fn doc_get_users() -> impl IntoResponse {
-    // 模擬資料
-    let users = vec![
+    // Json 包裝器會自動將 Struct 轉為 JSON 回傳
+    (StatusCode::OK, Json(sample_users()))
+}
+
+/// 模擬資料
+fn sample_users() -> Vec<User> {
+    vec![
         User {
             id: 1,
             username: "Alice".to_string(),
@@ -65,14 +150,22 @@ async fn doc_get_users() -> impl IntoResponse {
             username: "Bob".to_string(),
             email: "bob@example.com".to_string(),
         },
-    ];
-
-    // Json 包裝器會自動將 Struct 轉為 JSON 回傳
-    (StatusCode::OK, Json(users))
+    ]
 }
 
 /// POST /users 處理器
 /// Json<CreateUserPayload> 會自動解析 Request Body
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    summary = "建立使用者",
+    request_body = CreateUserPayload,
+    responses(
+        (status = 201, description = "建立的使用者，id 固定為 1337", body = User),
+        (status = 422, description = "JSON 格式錯誤或缺少欄位"),
+    ),
+)]
 async fn doc_create_user(Json(payload): Json<CreateUserPayload>) -> impl IntoResponse {
     // 這裡單純回傳一個模擬建立成功的 User
     let user = User {
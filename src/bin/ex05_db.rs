@@ -1,7 +1,8 @@
-use rusqlite::{params, Connection, Result};
+use rusqlite::{Connection, Result};
 
 #[derive(Debug)]
 struct Person {
+    #[allow(dead_code)] // 僅透過 Debug 輸出
     id: i32,
     name: String,
     data: Option<String>,
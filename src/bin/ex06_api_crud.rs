@@ -1,241 +1,1301 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    extract::{FromRequestParts, Path, Query, Request, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json,
 };
-use rusqlite::{params, Connection};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, params_from_iter, types::Value, Connection, ErrorCode, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+#[path = "../migrations/command.rs"]
+mod migrate_command;
+#[path = "../migrations/users_db.rs"]
+mod migrations;
+use migrate_command::MigrateCommand;
+
+#[path = "../pagination.rs"]
+mod pagination;
+use pagination::{contains_pattern, cursor_direction, effective_limit, parse_sort, Page, SortKey};
+
+#[path = "../merge_patch.rs"]
+mod merge_patch;
+use merge_patch::PatchField;
+
+#[path = "../etag.rs"]
+mod etag;
+use etag::{check_if_match, etag, not_modified, precondition_failed};
+
+#[path = "../auth.rs"]
+mod auth;
+use auth::{bearer_token, hash_password, validate_password, verify_password, Claims, JwtKeys, TokenPair, TokenType};
+
+#[path = "../policy.rs"]
+mod policy;
+
+#[path = "../rate_limit.rs"]
+mod rate_limit;
+use rate_limit::{Client, Quota, RateLimiter};
+
+#[path = "../telemetry.rs"]
+mod telemetry;
+
+#[path = "../metrics.rs"]
+mod metrics;
+
+#[path = "../health.rs"]
+mod health;
+
+#[path = "../shutdown.rs"]
+mod shutdown;
+use shutdown::Shutdown;
+
+#[path = "../http_layers.rs"]
+mod http_layers;
+use http_layers::HttpLayers;
+
+#[path = "../openapi.rs"]
+mod openapi;
+use openapi::BearerAuth;
+use policy::{Denied, Permission, Role};
+use sea_orm::ActiveEnum;
 
 /// 資料庫檔案名稱
 const DB_FILE: &str = "my_database.db";
+/// 連線池最大連線數
+const POOL_SIZE: u32 = 8;
+/// 連線池用盡時，等待可用連線的最長時間
+const POOL_TIMEOUT: Duration = Duration::from_secs(5);
+/// 遇到寫入鎖時 SQLite 自動重試的時間，超過才回傳 `database is locked`
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// JWT 簽章金鑰所在的環境變數
+const JWT_SECRET_ENV: &str = "JWT_SECRET";
 
 /// 使用者資料模型
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// `version` 每次更新 + 1，同時作為 ETag 使用
+/// 時間欄位由資料庫的 trigger 維護 (見 migration 0004)，`deleted_at` 不為 NULL 代表已軟刪除
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 struct User {
     id: i64,
     username: String,
     email: String,
+    role: Role,
+    version: i64,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+    deleted_at: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// 查詢 User 時選取的欄位，順序需與 `map_user` 一致
+const USER_COLUMNS: &str = "id, username, email, version, created_at, updated_at, deleted_at, role";
+
+/// 註冊時設定密碼，密碼只以 argon2 雜湊儲存，不會出現在任何回應中
+#[derive(Debug, Deserialize, ToSchema)]
 struct CreateUserPayload {
     username: String,
     email: String,
+    password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+struct LoginPayload {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct RefreshPayload {
+    refresh_token: String,
+}
+
+/// PUT /users/{id}/role 的 Payload
+#[derive(Debug, Deserialize, ToSchema)]
+struct AssignRolePayload {
+    role: Role,
+}
+
+/// 登出時可一併帶入 refresh token 將其撤銷
+#[derive(Debug, Default, Deserialize, ToSchema)]
+struct LogoutPayload {
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 struct UpdateUserPayload {
     username: String,
     email: String,
 }
 
-/// 應用程式狀態，包含資料庫連線
-/// 由於 Connection 不是 Thread-safe，且 Rusqlite 建議每個 Request 建立連線或使用 Connection Pool
-/// 這裡為了教學簡單，演示 "Connection Pool" 的概念
-/// 實務上建議使用 `r2d2` 或 `deadpool` 搭配 `rusqlite`
+/// PATCH 使用的部分更新 Payload，只有出現的欄位會被更新
+// `#[serde(default)]` 寫在欄位上而非 struct 上，OpenAPI 文件才不會把「沒出現」列為預設值
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchUserPayload {
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    username: PatchField<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    email: PatchField<String>,
+}
+
+/// GET /users 的查詢參數
+/// - `limit` / `offset`: 偏移分頁
+/// - `after`: 游標分頁，帶入上一頁回傳的 `next_cursor`
+/// - `sort`: 以逗號分隔的欄位，前綴 `-` 表示遞減，例如 `sort=username,-id`
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListUsersQuery {
+    limit: Option<u64>,
+    offset: Option<u64>,
+    after: Option<i64>,
+    sort: Option<String>,
+    username_contains: Option<String>,
+    email_contains: Option<String>,
+    /// 預設不列出已軟刪除的使用者；設為 true 需要 users:restore 權限
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+/// 應用程式狀態，包含資料庫連線池
+/// rusqlite 的 Connection 不是 Sync，無法在多個 Request 間共用同一條連線，
+/// 因此使用 `r2d2` 連線池：每個 Request 借出一條連線，用完自動歸還
 struct AppState {
-    // 這裡我們簡化處理：每次操作都打開一個新連線，或者用 Mutex 保護單一連線
-    // 但 SQLite 檔案式資料庫在多執行緒下，最佳實踐是使用連線池
-    // 為了範例簡單，我們直接在 Handler 中開啟連線，不透過 State 傳遞 Connection
-    db_path: String,
+    pool: Pool<SqliteConnectionManager>,
+    jwt: JwtKeys,
+    shutdown: Shutdown,
+}
+
+/// 已登入的使用者
+/// 需要登入的 handler 加上此參數即可，沒有有效的 access token 時直接回傳 401
+/// 角色每次請求都從資料庫讀取，變更角色後不必重新登入就會生效
+struct AuthUser {
+    id: i64,
+    role: Role,
+    claims: Claims,
+}
+
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers).ok_or_else(|| Denied::Unauthenticated.into_response())?;
+        let claims = state
+            .jwt
+            .verify(token, TokenType::Access)
+            .map_err(|_| unauthorized("Invalid or expired token"))?;
+
+        // 簽章有效之外，還要確認 token 沒有因登出而撤銷，且使用者沒有被刪除
+        let pool = state.pool.clone();
+        let (jti, user_id) = (claims.jti.clone(), claims.sub);
+        let span = tracing::info_span!(target: "db", "db");
+        let role = tokio::task::spawn_blocking(move || -> Result<Option<Role>, (StatusCode, String)> {
+            let _span = span.enter();
+            let conn = pool.get().map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+            conn.query_row(
+                "SELECT role FROM users
+                 WHERE id = ?2 AND deleted_at IS NULL
+                   AND NOT EXISTS (SELECT 1 FROM revoked_access_tokens WHERE jti = ?1)",
+                params![jti, user_id],
+                |row| row_role(row, 0),
+            )
+            .optional()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?
+        .map_err(IntoResponse::into_response)?;
+
+        match role {
+            Some(role) => Ok(AuthUser {
+                id: user_id,
+                role,
+                claims,
+            }),
+            None => Err(unauthorized("Token has been revoked")),
+        }
+    }
+}
+
+/// 帶有效 access token 的請求以使用者 id 計算限流額度，同一位使用者換 IP 仍共用額度
+/// 只驗證簽章 (不查資料庫)：無法偽造其他使用者的 id，撤銷等檢查仍由 [`AuthUser`] 負責；
+/// 無效的 token 以 IP 計算，由 handler 回傳 401
+async fn rate_limit_client(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let user_id = bearer_token(request.headers())
+        .and_then(|token| state.jwt.verify(token, TokenType::Access).ok())
+        .map(|claims| claims.sub);
+    if let Some(user_id) = user_id {
+        request.extensions_mut().insert(Client(format!("user:{}", user_id)));
+    }
+    next.run(request).await
+}
+
+// 401 回應，依 RFC 6750 附上 WWW-Authenticate
+fn unauthorized(message: &'static str) -> Response {
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], message).into_response()
 }
 
 /// 範例 06: RESTful API + SQLite CRUD
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // 1. 初始化資料庫
-    init_db(DB_FILE)?;
+    // 日誌格式與層級見 src/telemetry.rs (LOG_FORMAT / RUST_LOG)
+    telemetry::init().map_err(anyhow::Error::msg)?;
+
+    // 1. 建立連線池並套用資料庫 migration
+    let pool = create_pool(DB_FILE)?;
+    let mut conn = pool.get()?;
+
+    // 子命令 `migrate up | down [n] | status` 只處理 migration，不啟動伺服器
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match MigrateCommand::from_args(&args).map_err(anyhow::Error::msg)? {
+        Some(MigrateCommand::Up) => return migrations::migrate_up(&mut conn),
+        Some(MigrateCommand::Down(steps)) => return migrations::migrate_down(&mut conn, steps),
+        Some(MigrateCommand::Status) => return Ok(migrations::print_status(&conn)?),
+        None => {}
+    }
+    migrations::migrate_up(&mut conn)?;
+    drop(conn);
+    tracing::info!("資料庫初始化完成。");
 
     // 2. 共享狀態
+    // 未設定 JWT_SECRET 時使用隨機金鑰，重新啟動後所有人都需要重新登入
+    let jwt = match std::env::var(JWT_SECRET_ENV) {
+        Ok(secret) if !secret.is_empty() => JwtKeys::from_secret(secret.as_bytes()),
+        _ => {
+            tracing::warn!("{} is not set, using a random key; tokens will not survive a restart", JWT_SECRET_ENV);
+            JwtKeys::random()
+        }
+    };
+    // 關閉流程見 src/shutdown.rs (SHUTDOWN_DELAY / SHUTDOWN_TIMEOUT)
+    let shutdown = Shutdown::install().map_err(anyhow::Error::msg)?;
+    // CORS、壓縮、請求大小限制、逾時與安全性 header 見 src/http_layers.rs
+    let http_layers = HttpLayers::from_env().map_err(anyhow::Error::msg)?;
     let shared_state = Arc::new(AppState {
-        db_path: DB_FILE.to_string(),
+        pool: pool.clone(),
+        jwt,
+        shutdown: shutdown.clone(),
     });
+    // 限流設定見 src/rate_limit.rs，登入另外限制以防止暴力猜測密碼
+    let limiter = Arc::new(RateLimiter::from_env()?.with_route("/auth/login", Quota::per_minute(10)));
 
-    // 3. 建立路由
-    let app = Router::new()
-        .route("/users", get(list_users).post(create_user))
-        .route("/users/{id}", get(get_user).put(update_user).delete(delete_user))
-        .with_state(shared_state);
+    // 3. 建立路由，同時產生 OpenAPI 文件 (GET /openapi.json、GET /docs)
+    let app = api_routes()
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit::limit))
+        // 在限流外層先辨識登入的使用者，讓限流以使用者 id (而不是 IP) 計算額度
+        .route_layer(middleware::from_fn_with_state(shared_state.clone(), rate_limit_client))
+        // 加在限流之後，Prometheus 抓取指標與健康檢查不受限流影響
+        .routes(routes!(get_metrics))
+        .routes(routes!(health::healthz))
+        .routes(routes!(readyz));
+    let app = openapi::finish(app).with_state(shared_state);
+    let app = http_layers
+        .apply(app)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(telemetry::trace_request));
 
     // 4. 啟動伺服器
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("API Server running at http://{}", addr);
-    println!("Database file: {}", DB_FILE);
+    tracing::info!(database = DB_FILE, "API Server running at http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // 帶上連線資訊，限流才能以來源 IP 區分 client
+    // 收到 SIGINT / SIGTERM 後等處理中的請求完成，避免寫入到一半被中斷
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone().stop_accepting());
+    shutdown.drain(server).await?;
 
-    Ok(())
-}
+    // 5. 關閉資料庫連線
+    // 伺服器結束時 router 與 AppState 已釋放，這裡是連線池最後的 handle；
+    // 最後一條連線關閉時 SQLite 會把 WAL 寫回主檔案
+    drop(pool);
+    tracing::info!("資料庫連線已關閉，伺服器已停止");
 
-// 初始化資料庫表格
-fn init_db(path: &str) -> rusqlite::Result<()> {
-    let conn = Connection::open(path)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS users (
-            id       INTEGER PRIMARY KEY AUTOINCREMENT,
-            username TEXT NOT NULL,
-            email    TEXT NOT NULL
-        )",
-        (),
-    )?;
-    println!("資料庫初始化完成。");
     Ok(())
 }
 
-// 輔助函式：取得資料庫連線
-fn get_conn(state: &AppState) -> rusqlite::Result<Connection> {
-    Connection::open(&state.db_path)
+/// 使用者與登入相關的路由，各路由的說明寫在 handler 的 `#[utoipa::path]`
+fn api_routes() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(list_users, create_user))
+        .routes(routes!(get_user, update_user, patch_user, delete_user))
+        .routes(routes!(restore_user))
+        .routes(routes!(assign_role))
+        .routes(routes!(login))
+        .routes(routes!(refresh))
+        .routes(routes!(logout))
 }
 
-// --- Handlers ---
+/// OpenAPI 文件的標題、說明與驗證方式
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Users API", description = "範例 06: RESTful API + SQLite CRUD"),
+    modifiers(&BEARER_AUTH),
+)]
+struct ApiDoc;
 
-/// 取得所有使用者
-async fn list_users(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let conn = match get_conn(&state) {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+const BEARER_AUTH: BearerAuth = BearerAuth("POST /auth/login 取得的 access token");
 
-    let mut stmt = match conn.prepare("SELECT id, username, email FROM users") {
-        Ok(s) => s,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
-
-    let user_iter = stmt.query_map([], |row| {
-        Ok(User {
-            id: row.get(0)?,
-            username: row.get(1)?,
-            email: row.get(2)?,
-        })
+/// 建立 SQLite 連線池
+/// 每條新連線都會設定：
+/// - WAL 模式：讀取不會被寫入擋住，多條連線可同時讀
+/// - busy_timeout：寫入互相競爭時等待而非立即失敗
+/// - foreign_keys：SQLite 預設不檢查外鍵，需逐條連線開啟
+/// - profile：每條 SQL 執行完後記錄語句與耗時
+fn create_pool(path: &str) -> Result<Pool<SqliteConnectionManager>, r2d2::Error> {
+    let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+        conn.profile(Some(log_query));
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "foreign_keys", true)
     });
+    Pool::builder()
+        .max_size(POOL_SIZE)
+        .connection_timeout(POOL_TIMEOUT)
+        .build(manager)
+}
+
+// SQLite 只回報執行完成的語句 (耗時精確度為毫秒)，失敗的語句由 handler 回傳的錯誤記錄
+fn log_query(sql: &str, elapsed: Duration) {
+    telemetry::record_query(sql, elapsed, false);
+    metrics::observe_query("rusqlite", sql, elapsed, false);
+}
 
-    match user_iter {
-        Ok(iter) => {
-            let users: Vec<User> = iter.filter_map(Result::ok).collect();
-            (StatusCode::OK, Json(users)).into_response()
+/// 判斷錯誤是否為唯一限制衝突，若是則回傳衝突的欄位名稱
+/// SQLite 的錯誤訊息格式為 "UNIQUE constraint failed: users.username"
+fn unique_violation_field(err: &rusqlite::Error) -> Option<&'static str> {
+    match err {
+        rusqlite::Error::SqliteFailure(e, Some(msg)) if e.code == ErrorCode::ConstraintViolation => {
+            ["username", "email"]
+                .into_iter()
+                .find(|field| msg.contains(&format!("users.{}", field)))
         }
+        _ => None,
+    }
+}
+
+// 將資料庫錯誤轉為 HTTP 回應，唯一限制衝突回傳 409 並指出衝突欄位
+fn db_error_response(err: rusqlite::Error) -> Response {
+    match unique_violation_field(&err) {
+        Some(field) => (StatusCode::CONFLICT, format!("{} already exists", field)).into_response(),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// 輔助函式：從連線池借出連線，並在 blocking 執行緒上執行同步的 rusqlite 操作
+// rusqlite 的呼叫會阻塞執行緒，直接在 async handler 中執行會卡住 tokio 的 worker
+async fn with_conn<F>(state: &AppState, f: F) -> Response
+where
+    F: FnOnce(&Connection) -> Response + Send + 'static,
+{
+    let pool = state.pool.clone();
+    // blocking 執行緒不會繼承目前的 span，需要明確帶過去，SQL 的日誌才會歸在這個請求之下
+    let span = tracing::info_span!(target: "db", "db");
+    let result = tokio::task::spawn_blocking(move || {
+        let _span = span.enter();
+        // pool.get() 在連線用盡時會等待，同樣不能在 async 執行緒上呼叫
+        pool.get().map(|conn| f(&conn))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => response,
+        // 等不到可用連線，代表服務暫時過載
+        Ok(Err(e)) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 取得單一使用者
-async fn get_user(
+// --- Handlers ---
+
+/// 檢查資料庫可以連線、schema 已套用所有 migration
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "ops",
+    summary = "資料庫可以連線且 schema 為最新",
+    responses(
+        (status = 200, description = "可以接受請求", body = health::Readiness),
+        (status = 503, description = "有依賴無法使用，或正在關閉", body = health::Readiness),
+    ),
+)]
+async fn readyz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let (database, schema) = tokio::join!(
+        check_with_conn(&state, |conn| conn.query_row("SELECT 1", [], |_| Ok(())).map_err(|e| e.to_string())),
+        check_with_conn(&state, |conn| {
+            let pending = migrations::pending_migrations(conn).map_err(|e| e.to_string())?;
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let names: Vec<String> = pending.iter().map(|m| format!("{:04}_{}", m.version, m.name)).collect();
+            Err(format!("pending migrations: {}", names.join(", ")))
+        }),
+    );
+    health::readiness(state.shutdown.is_draining(), vec![("database", database), ("migrations", schema)])
+}
+
+// 在 blocking 執行緒上借連線執行健康檢查，等不到連線也算失敗
+async fn check_with_conn<F>(state: &AppState, f: F) -> health::Check
+where
+    F: FnOnce(&Connection) -> Result<(), String> + Send + 'static,
+{
+    let pool = state.pool.clone();
+    health::check(async move {
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get_timeout(health::CHECK_TIMEOUT).map_err(|e| e.to_string())?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    })
+    .await
+}
+
+/// Prometheus 指標，附上連線池目前的使用狀況
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "ops",
+    summary = "Prometheus 指標",
+    responses(
+        (status = 200, description = "Prometheus text format"),
+    ),
+)]
+async fn get_metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let pool = state.pool.state();
+    metrics::render(&[metrics::PoolStats {
+        backend: "rusqlite",
+        max: state.pool.max_size(),
+        open: pool.connections,
+        idle: pool.idle_connections,
+    }])
+}
+
+/// 取得使用者列表，支援分頁、排序與篩選
+/// 例: GET /users?limit=10&sort=-id&username_contains=al
+/// 不需要登入；`include_deleted=true` 會列出已刪除使用者的 email，只有可還原使用者的角色能用
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    summary = "取得使用者列表，支援分頁、排序與篩選",
+    params(
+        ListUsersQuery,
+    ),
+    responses(
+        (status = 200, description = "符合條件的使用者", body = Page<User>),
+        (status = 400, description = "排序欄位或游標不合法"),
+        (status = 401, description = "include_deleted=true 但沒有登入"),
+        (status = 403, description = "Missing permission: users:restore"),
+    ),
+)]
+async fn list_users(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<i64>,
+    user: Result<AuthUser, Response>,
+    Query(query): Query<ListUsersQuery>,
 ) -> impl IntoResponse {
-    let conn = match get_conn(&state) {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    if query.include_deleted {
+        let user = match user {
+            Ok(user) => user,
+            Err(rejection) => return rejection,
+        };
+        if let Err(denied) = user.role.authorize(false, Permission::RestoreUser) {
+            return denied.into_response();
+        }
+    }
+    let sort = match parse_sort(query.sort.as_deref(), &["id", "username", "email"]) {
+        Ok(s) => s,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
     };
+    let cursor_descending = cursor_direction(&sort);
+    if query.after.is_some() && cursor_descending.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "`after` can only be combined with sort=id or sort=-id",
+        )
+            .into_response();
+    }
+    let limit = effective_limit(query.limit);
+    let offset = query.offset.unwrap_or(0);
+
+    with_conn(&state, move |conn| {
+        // 組合 WHERE 條件：欄位名稱寫死在程式中，使用者輸入一律透過參數綁定
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if !query.include_deleted {
+            conditions.push("deleted_at IS NULL".to_string());
+        }
+        if let Some(needle) = &query.username_contains {
+            values.push(Value::Text(contains_pattern(needle)));
+            conditions.push(format!("username LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+        if let Some(needle) = &query.email_contains {
+            values.push(Value::Text(contains_pattern(needle)));
+            conditions.push(format!("email LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+
+        // total 只套用篩選條件，不受游標與分頁影響
+        let total: i64 = match conn.query_row(
+            &format!("SELECT COUNT(*) FROM users{}", where_clause(&conditions)),
+            params_from_iter(&values),
+            |row| row.get(0),
+        ) {
+            Ok(n) => n,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+
+        if let Some(after) = query.after {
+            values.push(Value::Integer(after));
+            let op = if cursor_descending == Some(true) { "<" } else { ">" };
+            conditions.push(format!("id {} ?{}", op, values.len()));
+        }
+
+        // 多取一筆用來判斷是否還有下一頁
+        values.push(Value::Integer((limit + 1) as i64));
+        let limit_param = values.len();
+        values.push(Value::Integer(offset as i64));
+        let offset_param = values.len();
+
+        let sql = format!(
+            "SELECT {} FROM users{} ORDER BY {} LIMIT ?{} OFFSET ?{}",
+            USER_COLUMNS,
+            where_clause(&conditions),
+            order_by_clause(&sort),
+            limit_param,
+            offset_param,
+        );
+        let mut stmt = match conn.prepare(&sql) {
+            Ok(s) => s,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+
+        let user_iter = stmt.query_map(params_from_iter(&values), map_user);
+
+        match user_iter {
+            Ok(iter) => {
+                let mut users: Vec<User> = iter.filter_map(Result::ok).collect();
+                let has_more = users.len() as u64 > limit;
+                users.truncate(limit as usize);
+                let next_cursor = match (has_more, cursor_descending) {
+                    (true, Some(_)) => users.last().map(|u| u.id),
+                    _ => None,
+                };
+                let page = Page {
+                    items: users,
+                    total: total as u64,
+                    limit,
+                    offset,
+                    next_cursor,
+                };
+                (StatusCode::OK, Json(page)).into_response()
+            }
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+// 將條件串成 " WHERE a AND b"，沒有條件時回傳空字串
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+// 將排序欄位轉為 ORDER BY 子句，最後以 id 作為次要排序確保結果穩定
+fn order_by_clause(sort: &[SortKey]) -> String {
+    let mut parts: Vec<String> = sort
+        .iter()
+        .map(|k| format!("{} {}", k.field, if k.descending { "DESC" } else { "ASC" }))
+        .collect();
+    if !sort.iter().any(|k| k.field == "id") {
+        parts.push("id ASC".to_string());
+    }
+    parts.join(", ")
+}
+
+// 將查詢結果的一列轉為 User，欄位順序見 USER_COLUMNS
+fn map_user(row: &rusqlite::Row) -> rusqlite::Result<User> {
+    Ok(User {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        email: row.get(2)?,
+        version: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        deleted_at: row.get(6)?,
+        role: row_role(row, 7)?,
+    })
+}
+
+// users.role 以字串儲存，轉為 Role (有 CHECK 限制，正常情況不會失敗)
+fn row_role(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Role> {
+    let value: String = row.get(idx)?;
+    Role::try_from_value(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, e.to_string().into())
+    })
+}
 
-    // query_row 回傳單筆資料，若無資料會回傳 Error::QueryReturnedNoRows
-    let user = conn.query_row(
-        "SELECT id, username, email FROM users WHERE id = ?1",
+// 查詢單一未刪除的使用者，若無資料會回傳 Error::QueryReturnedNoRows
+fn find_user(conn: &Connection, id: i64) -> rusqlite::Result<User> {
+    conn.query_row(
+        &format!("SELECT {} FROM users WHERE id = ?1 AND deleted_at IS NULL", USER_COLUMNS),
         params![id],
-        |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                email: row.get(2)?,
-            })
-        },
-    );
+        map_user,
+    )
+}
+
+// 查詢單一已軟刪除的使用者，供還原使用
+fn find_deleted_user(conn: &Connection, id: i64) -> rusqlite::Result<User> {
+    conn.query_row(
+        &format!("SELECT {} FROM users WHERE id = ?1 AND deleted_at IS NOT NULL", USER_COLUMNS),
+        params![id],
+        map_user,
+    )
+}
 
-    match user {
-        Ok(u) => (StatusCode::OK, Json(u)).into_response(),
+// 寫入後重新讀取完整資料 (包含 trigger 維護的時間欄位) 並回傳
+fn reload_user_response(conn: &Connection, status: StatusCode, id: i64) -> Response {
+    match find_user(conn, id) {
+        Ok(u) => user_response(status, u),
         Err(rusqlite::Error::QueryReturnedNoRows) => (StatusCode::NOT_FOUND, "User not found").into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 建立使用者
+// 回傳單一使用者並附上 ETag，讓客戶端後續可用 If-Match 更新
+fn user_response(status: StatusCode, user: User) -> Response {
+    (status, [(header::ETAG, etag(user.version))], Json(user)).into_response()
+}
+
+/// 取得單一使用者
+/// 若 If-None-Match 與目前 ETag 相同則回傳 304，不重送內容
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    summary = "取得單一使用者",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-None-Match" = Option<String>, Header, description = "先前取得的 ETag，內容沒有變更時回傳 304"),
+    ),
+    responses(
+        (status = 200, description = "使用者，ETag 為目前的版本", body = User),
+        (status = 304, description = "內容沒有變更"),
+        (status = 404, description = "User not found"),
+    ),
+)]
+async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    with_conn(&state, move |conn| {
+        match find_user(conn, id) {
+            Ok(u) if not_modified(&headers, u.version) => {
+                (StatusCode::NOT_MODIFIED, [(header::ETAG, etag(u.version))]).into_response()
+            }
+            Ok(u) => user_response(StatusCode::OK, u),
+            Err(rusqlite::Error::QueryReturnedNoRows) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+/// 建立使用者 (註冊)，不需要登入
+/// 新使用者的角色為 reader；系統中還沒有能登入的 admin (未刪除且設有密碼) 時，第一位註冊的使用者成為 admin
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    summary = "註冊使用者，第一個使用者成為 admin",
+    request_body = CreateUserPayload,
+    responses(
+        (status = 201, description = "建立的使用者", body = User),
+        (status = 400, description = "密碼不符合要求"),
+        (status = 409, description = "username 或 email 已存在"),
+    ),
+)]
 async fn create_user(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateUserPayload>,
 ) -> impl IntoResponse {
-    let conn = match get_conn(&state) {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+    if let Err(msg) = validate_password(&payload.password) {
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
 
-    // 執行插入，並取得自動生成的 ID
-    let result = conn.execute(
-        "INSERT INTO users (username, email) VALUES (?1, ?2)",
-        params![payload.username, payload.email],
-    );
+    with_conn(&state, move |conn| {
+        // argon2 很耗 CPU，在 with_conn 的 blocking 執行緒上計算
+        let password_hash = match hash_password(&payload.password) {
+            Ok(hash) => hash,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        // 執行插入，並取得自動生成的 ID
+        let result = conn.execute(
+            "INSERT INTO users (username, email, password_hash, role)
+             VALUES (?1, ?2, ?3, CASE WHEN EXISTS (SELECT 1 FROM users
+                                                   WHERE role = 'admin' AND deleted_at IS NULL AND password_hash IS NOT NULL)
+                                      THEN 'reader' ELSE 'admin' END)",
+            params![payload.username, payload.email, password_hash],
+        );
 
-    match result {
-        Ok(_) => {
-            let id = conn.last_insert_rowid();
-            let new_user = User {
-                id,
-                username: payload.username,
-                email: payload.email,
-            };
-            (StatusCode::CREATED, Json(new_user)).into_response()
+        match result {
+            Ok(_) => reload_user_response(conn, StatusCode::CREATED, conn.last_insert_rowid()),
+            Err(e) => db_error_response(e),
         }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
+    })
+    .await
 }
 
 /// 更新使用者
+/// 必須帶 If-Match，且 UPDATE 以 `version = ?` 為條件，避免覆蓋他人的修改
+/// 只能修改自己的資料，修改別人需要 users:update 權限
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    summary = "整筆更新使用者 (本人或 users:update)",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = UpdateUserPayload,
+    responses(
+        (status = 200, description = "更新後的使用者", body = User),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:update"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "username 或 email 已存在"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = [])),
+)]
 async fn update_user(
     State(state): State<Arc<AppState>>,
+    user: AuthUser,
     Path(id): Path<i64>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateUserPayload>,
 ) -> impl IntoResponse {
-    let conn = match get_conn(&state) {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+    if let Err(denied) = user.role.authorize(user.id == id, Permission::UpdateAnyUser) {
+        return denied.into_response();
+    }
 
-    let result = conn.execute(
-        "UPDATE users SET username = ?1, email = ?2 WHERE id = ?3",
-        params![payload.username, payload.email, id],
-    );
+    with_conn(&state, move |conn| {
+        let current = match find_user(conn, id) {
+            Ok(u) => u,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        if let Err(rejection) = check_if_match(&headers, current.version) {
+            return rejection.into_response();
+        }
 
-    match result {
-        Ok(affected) => {
-            if affected == 0 {
-                (StatusCode::NOT_FOUND, "User not found").into_response()
-            } else {
-                let updated_user = User {
-                    id,
-                    username: payload.username,
-                    email: payload.email,
-                };
-                (StatusCode::OK, Json(updated_user)).into_response()
-            }
+        let result = conn.execute(
+            "UPDATE users SET username = ?1, email = ?2, version = version + 1
+             WHERE id = ?3 AND version = ?4",
+            params![payload.username, payload.email, id, current.version],
+        );
+
+        match result {
+            Ok(0) => precondition_failed().into_response(),
+            Ok(_) => reload_user_response(conn, StatusCode::OK, id),
+            Err(e) => db_error_response(e),
         }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    })
+    .await
+}
+
+/// 部分更新使用者 (JSON Merge Patch)
+/// 例: PATCH /users/1 {"email": "new@example.com"} 只會更新 email
+/// 權限規則與 PUT 相同
+#[utoipa::path(
+    patch,
+    path = "/users/{id}",
+    tag = "users",
+    summary = "部分更新使用者 (JSON Merge Patch)",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = PatchUserPayload,
+    responses(
+        (status = 200, description = "更新後的使用者", body = User),
+        (status = 400, description = "欄位不可為 null"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:update"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "username 或 email 已存在"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn patch_user(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<PatchUserPayload>,
+) -> impl IntoResponse {
+    if let Err(denied) = user.role.authorize(user.id == id, Permission::UpdateAnyUser) {
+        return denied.into_response();
     }
+
+    // username / email 都是 NOT NULL，不允許以 null 清除
+    let (username, email) = match (
+        payload.username.required("username"),
+        payload.email.required("email"),
+    ) {
+        (Ok(u), Ok(e)) => (u, e),
+        (Err(msg), _) | (_, Err(msg)) => return (StatusCode::BAD_REQUEST, msg).into_response(),
+    };
+
+    with_conn(&state, move |conn| {
+        let current = match find_user(conn, id) {
+            Ok(u) => u,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        if let Err(rejection) = check_if_match(&headers, current.version) {
+            return rejection.into_response();
+        }
+
+        // 只組出有提供的欄位
+        let mut assignments: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(username) = username {
+            values.push(Value::Text(username));
+            assignments.push(format!("username = ?{}", values.len()));
+        }
+        if let Some(email) = email {
+            values.push(Value::Text(email));
+            assignments.push(format!("email = ?{}", values.len()));
+        }
+
+        if assignments.is_empty() {
+            return user_response(StatusCode::OK, current);
+        }
+
+        values.push(Value::Integer(id));
+        let id_param = values.len();
+        values.push(Value::Integer(current.version));
+        let version_param = values.len();
+        let sql = format!(
+            "UPDATE users SET {}, version = version + 1 WHERE id = ?{} AND version = ?{}",
+            assignments.join(", "),
+            id_param,
+            version_param,
+        );
+        match conn.execute(&sql, params_from_iter(&values)) {
+            Ok(0) => precondition_failed().into_response(),
+            // 回傳更新後的完整資料
+            Ok(_) => reload_user_response(conn, StatusCode::OK, id),
+            Err(e) => db_error_response(e),
+        }
+    })
+    .await
 }
 
-/// 刪除使用者
+/// 刪除使用者 (軟刪除)
+/// 只設定 deleted_at，資料仍保留並可透過 POST /users/{id}/restore 還原
+/// 已刪除使用者的 username / email 仍保留唯一限制，確保還原時不會衝突
+/// 只有 admin (users:delete) 可以刪除使用者，包含刪除自己；不能刪除最後一位 admin
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    summary = "軟刪除使用者 (users:delete)",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "User deleted"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:delete"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Cannot remove the last admin"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = [])),
+)]
 async fn delete_user(
     State(state): State<Arc<AppState>>,
+    user: AuthUser,
     Path(id): Path<i64>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    let conn = match get_conn(&state) {
-        Ok(c) => c,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    };
+    if let Err(denied) = user.role.authorize(false, Permission::DeleteUser) {
+        return denied.into_response();
+    }
 
-    let result = conn.execute("DELETE FROM users WHERE id = ?1", params![id]);
+    with_conn(&state, move |conn| {
+        let current = match find_user(conn, id) {
+            Ok(u) => u,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        if let Err(rejection) = check_if_match(&headers, current.version) {
+            return rejection.into_response();
+        }
 
-    match result {
-        Ok(affected) => {
-             if affected == 0 {
-                (StatusCode::NOT_FOUND, "User not found").into_response()
-            } else {
-                (StatusCode::OK, "User deleted").into_response()
+        // 與變更角色相同，最後一位 admin 的檢查寫在同一個 UPDATE 中
+        let result = conn.execute(
+            &format!(
+                "UPDATE users SET deleted_at = {}, version = version + 1
+                 WHERE id = ?1 AND version = ?2 AND deleted_at IS NULL
+                   AND (role <> 'admin'
+                        OR EXISTS (SELECT 1 FROM users
+                                   WHERE role = 'admin' AND deleted_at IS NULL AND password_hash IS NOT NULL
+                                     AND id <> ?1))",
+                migrations::NOW_SQL
+            ),
+            params![id, current.version],
+        );
+
+        match result {
+            Ok(0) => match find_user(conn, id) {
+                Ok(u) if u.version == current.version && u.deleted_at.is_none() => {
+                    (StatusCode::CONFLICT, "Cannot remove the last admin").into_response()
+                }
+                _ => precondition_failed().into_response(),
+            },
+            Ok(_) => (StatusCode::OK, "User deleted").into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+/// 還原已軟刪除的使用者
+/// 與刪除相同需要帶 If-Match (刪除後回傳的 version 已 + 1，需以 GET /users?include_deleted=true 取得，
+/// 同樣需要 users:restore 權限)
+/// 需要 users:restore 權限
+#[utoipa::path(
+    post,
+    path = "/users/{id}/restore",
+    tag = "users",
+    summary = "還原已刪除的使用者 (users:restore)",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "還原後的使用者", body = User),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:restore"),
+        (status = 404, description = "Deleted user not found"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn restore_user(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(denied) = user.role.authorize(false, Permission::RestoreUser) {
+        return denied.into_response();
+    }
+
+    with_conn(&state, move |conn| {
+        let current = match find_deleted_user(conn, id) {
+            Ok(u) => u,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return (StatusCode::NOT_FOUND, "Deleted user not found").into_response()
             }
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        if let Err(rejection) = check_if_match(&headers, current.version) {
+            return rejection.into_response();
         }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+
+        let result = conn.execute(
+            "UPDATE users SET deleted_at = NULL, version = version + 1
+             WHERE id = ?1 AND version = ?2 AND deleted_at IS NOT NULL",
+            params![id, current.version],
+        );
+
+        match result {
+            Ok(0) => precondition_failed().into_response(),
+            Ok(_) => reload_user_response(conn, StatusCode::OK, id),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+/// 指派使用者角色，例: PUT /users/2/role {"role": "editor"}
+/// 需要 users:assign-role 權限與 If-Match；不能拿掉最後一位 admin 的角色
+#[utoipa::path(
+    put,
+    path = "/users/{id}/role",
+    tag = "users",
+    summary = "指派角色 (users:assign-role)",
+    params(
+        ("id" = i64, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = AssignRolePayload,
+    responses(
+        (status = 200, description = "更新後的使用者", body = User),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:assign-role"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Cannot remove the last admin"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn assign_role(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<AssignRolePayload>,
+) -> impl IntoResponse {
+    if let Err(denied) = user.role.authorize(false, Permission::AssignRole) {
+        return denied.into_response();
     }
+
+    with_conn(&state, move |conn| {
+        let current = match find_user(conn, id) {
+            Ok(u) => u,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        if let Err(rejection) = check_if_match(&headers, current.version) {
+            return rejection.into_response();
+        }
+        if current.role == payload.role {
+            return user_response(StatusCode::OK, current);
+        }
+
+        // 條件寫在同一個 UPDATE 中，兩位 admin 同時互相降級時不會都成功
+        let result = conn.execute(
+            "UPDATE users SET role = ?1, version = version + 1
+             WHERE id = ?2 AND version = ?3
+               AND (role <> 'admin'
+                    OR EXISTS (SELECT 1 FROM users
+                               WHERE role = 'admin' AND deleted_at IS NULL AND password_hash IS NOT NULL AND id <> ?2))",
+            params![payload.role.to_value(), id, current.version],
+        );
+
+        match result {
+            // 版本沒變代表是最後一位 admin 的條件擋下了更新
+            Ok(0) => match find_user(conn, id) {
+                Ok(u) if u.version == current.version => {
+                    (StatusCode::CONFLICT, "Cannot remove the last admin").into_response()
+                }
+                _ => precondition_failed().into_response(),
+            },
+            Ok(_) => reload_user_response(conn, StatusCode::OK, id),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+// 簽發一組 access / refresh token，並記錄 refresh token 以便之後撤銷
+fn issue_token_pair(conn: &Connection, jwt: &JwtKeys, user_id: i64) -> Response {
+    let (access_token, refresh_token, refresh_claims) = match (
+        jwt.issue(user_id, TokenType::Access),
+        jwt.issue(user_id, TokenType::Refresh),
+    ) {
+        (Ok((access, _)), Ok((refresh, claims))) => (access, refresh, claims),
+        (Err(e), _) | (_, Err(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let result = conn.execute(
+        "INSERT INTO refresh_tokens (jti, user_id, expires_at) VALUES (?1, ?2, ?3)",
+        params![refresh_claims.jti, user_id, refresh_claims.exp],
+    );
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    let pair = TokenPair {
+        access_token,
+        refresh_token,
+        token_type: "Bearer",
+        expires_in: auth::ACCESS_TOKEN_TTL.as_secs(),
+    };
+    (StatusCode::OK, Json(pair)).into_response()
+}
+
+/// 以帳號密碼登入，取得 access token 與 refresh token
+/// 帳號不存在與密碼錯誤回傳相同的訊息，避免被用來探測帳號
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    summary = "以帳號密碼登入",
+    request_body = LoginPayload,
+    responses(
+        (status = 200, description = "access token 與 refresh token", body = TokenPair),
+        (status = 401, description = "Invalid username or password"),
+        (status = 429, description = "嘗試次數過多"),
+    ),
+)]
+async fn login(State(state): State<Arc<AppState>>, Json(payload): Json<LoginPayload>) -> impl IntoResponse {
+    let app = state.clone();
+    with_conn(&state, move |conn| {
+        let row = conn
+            .query_row(
+                "SELECT id, password_hash FROM users WHERE username = ?1 AND deleted_at IS NULL",
+                params![payload.username],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional();
+
+        match row {
+            Ok(Some((id, Some(hash)))) if verify_password(&payload.password, &hash) => {
+                issue_token_pair(conn, &app.jwt, id)
+            }
+            Ok(_) => unauthorized("Invalid username or password"),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+/// 以 refresh token 換發新的一組 token
+/// 舊的 refresh token 隨即撤銷 (rotation)；若已撤銷的 refresh token 又被使用，
+/// 代表它可能已外洩，該使用者所有的 refresh token 一併撤銷，需要重新登入
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    tag = "auth",
+    summary = "以 refresh token 換發新的一組 token，舊的隨即失效",
+    request_body = RefreshPayload,
+    responses(
+        (status = 200, description = "新的 access token 與 refresh token", body = TokenPair),
+        (status = 401, description = "refresh token 無效、過期或已撤銷"),
+    ),
+)]
+async fn refresh(State(state): State<Arc<AppState>>, Json(payload): Json<RefreshPayload>) -> impl IntoResponse {
+    let claims = match state.jwt.verify(&payload.refresh_token, TokenType::Refresh) {
+        Ok(claims) => claims,
+        Err(_) => return unauthorized("Invalid or expired refresh token"),
+    };
+
+    let app = state.clone();
+    with_conn(&state, move |conn| {
+        let row = conn
+            .query_row(
+                "SELECT r.revoked_at IS NOT NULL FROM refresh_tokens r
+                 JOIN users u ON u.id = r.user_id AND u.deleted_at IS NULL
+                 WHERE r.jti = ?1 AND r.user_id = ?2",
+                params![claims.jti, claims.sub],
+                |row| row.get::<_, bool>(0),
+            )
+            .optional();
+
+        match row {
+            Ok(Some(false)) => {}
+            Ok(Some(true)) => {
+                if let Err(e) = conn.execute(
+                    &format!(
+                        "UPDATE refresh_tokens SET revoked_at = {} WHERE user_id = ?1 AND revoked_at IS NULL",
+                        migrations::NOW_SQL
+                    ),
+                    params![claims.sub],
+                ) {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+                }
+                return unauthorized("Refresh token has been revoked");
+            }
+            Ok(None) => return unauthorized("Invalid or expired refresh token"),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+
+        // 以 revoked_at IS NULL 為條件撤銷，兩個請求同時換發時只有一個會成功
+        let revoked = conn.execute(
+            &format!(
+                "UPDATE refresh_tokens SET revoked_at = {} WHERE jti = ?1 AND revoked_at IS NULL",
+                migrations::NOW_SQL
+            ),
+            params![claims.jti],
+        );
+        match revoked {
+            Ok(0) => unauthorized("Refresh token has been revoked"),
+            Ok(_) => issue_token_pair(conn, &app.jwt, claims.sub),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
+}
+
+/// 登出：撤銷目前的 access token，若有帶 refresh token 也一併撤銷
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    summary = "登出，撤銷目前的 access token 與 (可選的) refresh token",
+    request_body = Option<LogoutPayload>,
+    responses(
+        (status = 204, description = "已登出"),
+        (status = 400, description = "Invalid refresh token"),
+        (status = 401, description = "Authentication required"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn logout(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    payload: Option<Json<LogoutPayload>>,
+) -> impl IntoResponse {
+    let Json(payload) = payload.unwrap_or_default();
+    let refresh_jti = match payload.refresh_token {
+        Some(token) => match state.jwt.verify(&token, TokenType::Refresh) {
+            Ok(claims) if claims.sub == user.id => Some(claims.jti),
+            _ => return (StatusCode::BAD_REQUEST, "Invalid refresh token").into_response(),
+        },
+        None => None,
+    };
+
+    with_conn(&state, move |conn| {
+        let result = conn
+            .execute(
+                "INSERT OR IGNORE INTO revoked_access_tokens (jti, expires_at) VALUES (?1, ?2)",
+                params![user.claims.jti, user.claims.exp],
+            )
+            .and_then(|_| match &refresh_jti {
+                Some(jti) => conn.execute(
+                    &format!(
+                        "UPDATE refresh_tokens SET revoked_at = {} WHERE jti = ?1 AND revoked_at IS NULL",
+                        migrations::NOW_SQL
+                    ),
+                    params![jti],
+                ),
+                None => Ok(0),
+            })
+            // 已過期的 access token 本來就無法使用，不必再保留撤銷紀錄
+            .and_then(|_| {
+                conn.execute(
+                    "DELETE FROM revoked_access_tokens WHERE expires_at < ?1",
+                    params![auth::unix_now()],
+                )
+            });
+
+        match result {
+            Ok(_) => StatusCode::NO_CONTENT.into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    })
+    .await
 }
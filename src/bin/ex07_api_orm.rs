@@ -1,171 +1,2630 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    extract::{FromRequestParts, MatchedPath, Path, Query, Request, State},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json,
 };
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, Database, DatabaseConnection, EntityTrait, Schema, ConnectionTrait
+    prelude::DateTimeUtc,
+    sea_query::{Expr, LikeExpr, OnConflict},
+    ActiveEnum, ActiveModelBehavior, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectOptions, Database,
+    DatabaseConnection, DbErr, EntityTrait,
+    FromQueryResult, ModelTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait, ConnectionTrait, SqlErr, Statement, TransactionTrait
 };
-use serde::Deserialize;
+use sea_orm_migration::MigratorTrait;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeSet, HashMap};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
 
 // 引入 Entity 定義
 // 在 main.rs 或 lib.rs 需要宣告 mod entity;
 #[path = "../entity.rs"]
 mod entity;
 use entity::ActiveModel as PostActiveModel;
+use entity::Column as PostColumn;
 use entity::Entity as Post;
+use entity::PostStatus;
+
+#[path = "../user_entity.rs"]
+mod user_entity;
+use user_entity::Column as UserColumn;
+use user_entity::Entity as User;
+
+#[path = "../comment_entity.rs"]
+mod comment_entity;
+use comment_entity::ActiveModel as CommentActiveModel;
+use comment_entity::Column as CommentColumn;
+use comment_entity::Entity as Comment;
+
+#[path = "../tag_entity.rs"]
+mod tag_entity;
+use tag_entity::Column as TagColumn;
+use tag_entity::Entity as Tag;
+
+#[path = "../slug_redirect_entity.rs"]
+mod slug_redirect_entity;
+use slug_redirect_entity::Entity as SlugRedirect;
+
+#[path = "../slug.rs"]
+mod slug;
+
+#[path = "../revision_entity.rs"]
+mod revision_entity;
+use revision_entity::ActiveModel as RevisionActiveModel;
+use revision_entity::Column as RevisionColumn;
+use revision_entity::Entity as Revision;
+
+#[path = "../post_tag_entity.rs"]
+mod post_tag_entity;
+use post_tag_entity::Column as PostTagColumn;
+use post_tag_entity::Entity as PostTag;
+
+#[path = "../migrations/command.rs"]
+mod migrate_command;
+#[path = "../migrations/posts_db.rs"]
+mod migrations;
+use migrate_command::MigrateCommand;
+use migrations::Migrator;
+
+#[path = "../schema_check.rs"]
+mod schema_check;
+
+#[path = "../pagination.rs"]
+mod pagination;
+use pagination::{contains_pattern, cursor_direction, effective_limit, parse_sort, Page};
+
+#[path = "../merge_patch.rs"]
+mod merge_patch;
+use merge_patch::PatchField;
+
+#[path = "../etag.rs"]
+mod etag;
+use etag::{check_if_match, etag, not_modified, precondition_failed, variant_etag, variant_not_modified};
+
+#[path = "../markdown.rs"]
+mod markdown;
+use markdown::RenderCache;
+
+#[path = "../auth.rs"]
+#[allow(dead_code)] // refresh token 只在 ex06 使用
+mod auth;
+use auth::{bearer_token, hash_password, validate_password, verify_password, JwtKeys, TokenType};
+
+#[path = "../policy.rs"]
+mod policy;
+use policy::{Denied, Permission, Role};
+
+#[path = "../api_key.rs"]
+mod api_key;
+use api_key::{Access, Resource, Scope, Scopes};
+
+#[path = "../rate_limit.rs"]
+mod rate_limit;
+use rate_limit::{Client, Quota, RateLimiter};
+
+#[path = "../api_key_entity.rs"]
+mod api_key_entity;
+use api_key_entity::ActiveModel as ApiKeyActiveModel;
+use api_key_entity::Column as ApiKeyColumn;
+use api_key_entity::Entity as ApiKey;
+
+#[path = "../telemetry.rs"]
+mod telemetry;
+
+#[path = "../metrics.rs"]
+mod metrics;
+
+#[path = "../health.rs"]
+mod health;
+
+#[path = "../shutdown.rs"]
+mod shutdown;
+use shutdown::Shutdown;
+
+#[path = "../http_layers.rs"]
+mod http_layers;
+use http_layers::HttpLayers;
+
+#[path = "../openapi.rs"]
+mod openapi;
+use openapi::{ApiKeyAuth, BearerAuth};
 
 const DB_URL: &str = "sqlite://posts.db?mode=rwc";
+/// 編輯用的 Bearer token 所在的環境變數，給不屬於任何使用者的腳本使用
+const EDITOR_TOKEN_ENV: &str = "POSTS_EDITOR_TOKEN";
+/// JWT 簽章金鑰所在的環境變數
+/// 與 ex06 的 JWT_SECRET 分開：兩個資料庫的 users.id 不同，不能互相沿用 token
+const JWT_SECRET_ENV: &str = "POSTS_JWT_SECRET";
+/// 建立文章時 slug 衝突的最多嘗試次數
+const SLUG_INSERT_ATTEMPTS: u32 = 3;
 
 #[derive(Clone)]
 struct AppState {
     conn: DatabaseConnection,
+    editor_token: Option<Arc<str>>,
+    jwt: Arc<JwtKeys>,
+    render_cache: Arc<RenderCache>,
+    shutdown: Shutdown,
+}
+
+/// 發出請求的身分
+/// - `Authorization: Bearer <access token>`: 以 POST /auth/login 取得，角色每次從 users 表讀取
+/// - `X-API-Key: pk_...` 或 `Authorization: Bearer pk_...`: 代表建立金鑰的使用者，
+///   但只能使用金鑰 scopes 內的權限，由 `api_key_auth` middleware 驗證後放進 request extensions
+/// - `Authorization: Bearer <POSTS_EDITOR_TOKEN>`: 擁有 editor 權限的腳本，不是任何文章的作者
+///
+/// 沒有 `Authorization` header 時是匿名讀者，只能讀取公開的文章；
+/// 有帶 header 但無法驗證 (token 無效、過期或使用者已不存在) 時回傳 401，讓客戶端知道需要重新登入
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Viewer {
+    Anonymous,
+    Service,
+    User { id: i32, role: Role },
+}
+
+impl FromRequestParts<AppState> for Viewer {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if let Some(viewer) = parts.extensions.get::<Viewer>() {
+            return Ok(*viewer);
+        }
+        if !parts.headers.contains_key(header::AUTHORIZATION) {
+            return Ok(Viewer::Anonymous);
+        }
+        let Some(token) = bearer_token(&parts.headers) else {
+            return Err(unauthorized("Authorization must be a Bearer token"));
+        };
+        if state.editor_token.as_deref().is_some_and(|expected| constant_time_eq(token, expected)) {
+            return Ok(Viewer::Service);
+        }
+        let Some(user_id) = state
+            .jwt
+            .verify(token, TokenType::Access)
+            .ok()
+            .and_then(|claims| i32::try_from(claims.sub).ok())
+        else {
+            return Err(unauthorized("Invalid or expired token"));
+        };
+
+        match User::find_by_id(user_id).one(&state.conn).await {
+            Ok(Some(user)) => Ok(Viewer::User {
+                id: user.id,
+                role: user.role,
+            }),
+            Ok(None) => Err(unauthorized("User no longer exists")),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+        }
+    }
+}
+
+// 401 回應，依 RFC 6750 附上 WWW-Authenticate
+fn unauthorized(message: &'static str) -> Response {
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], message).into_response()
+}
+
+/// 帶 API key 的請求在進入 handler 前先驗證金鑰與 scope
+/// 以 route_layer 套用，所有路由 (包含不需要登入的讀取) 都受 scope 限制；
+/// 驗證通過後放入 [`Client`]，讓內層的限流以金鑰 (而不是 IP) 計算額度
+async fn api_key_auth(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(key) = api_key::from_headers(request.headers()) else {
+        // 帶了 X-API-Key 卻不是金鑰格式時回傳 401，而不是當成匿名讀者
+        if request.headers().contains_key(api_key::API_KEY_HEADER) {
+            return unauthorized("Invalid API key");
+        }
+        return next.run(request).await;
+    };
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(request.uri().path(), MatchedPath::as_str);
+    let scope = required_scope(request.method(), path);
+
+    match authenticate_api_key(&state.conn, key, scope).await {
+        Ok((key_id, viewer)) => {
+            request.extensions_mut().insert(viewer);
+            request.extensions_mut().insert(Client(format!("api-key:{}", key_id)));
+            next.run(request).await
+        }
+        Err(response) => response,
+    }
+}
+
+// 以 API key 驗證身分，回傳金鑰 id 與使用者；金鑰無效或過期回傳 401，缺少這個路由需要的 scope 回傳 403
+async fn authenticate_api_key(
+    conn: &DatabaseConnection,
+    key: &str,
+    scope: Option<Scope>,
+) -> Result<(i32, Viewer), Response> {
+    let found = ApiKey::find()
+        .filter(ApiKeyColumn::KeyHash.eq(api_key::hash_key(key)))
+        .find_also_related(User)
+        .one(conn)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    let Some((api_key, Some(user))) = found else {
+        return Err(unauthorized("Invalid API key"));
+    };
+    let now = chrono::Utc::now();
+    if api_key.expires_at.is_some_and(|t| t <= now) {
+        return Err(unauthorized("API key has expired"));
+    }
+
+    let Some(scope) = scope else {
+        return Err((StatusCode::FORBIDDEN, "API keys cannot be used for this endpoint").into_response());
+    };
+    if !Scopes::parse(&api_key.scopes).unwrap_or_default().allows(scope) {
+        return Err((StatusCode::FORBIDDEN, format!("Missing scope: {}", scope)).into_response());
+    }
+
+    // 每把金鑰最多每分鐘寫入一次 last_used_at，避免每個請求都要搶 SQLite 的寫入鎖
+    if api_key.last_used_at.is_none_or(|t| now - t >= chrono::Duration::minutes(1)) {
+        ApiKey::update_many()
+            .col_expr(ApiKeyColumn::LastUsedAt, Expr::value(now))
+            .filter(ApiKeyColumn::Id.eq(api_key.id))
+            .exec(conn)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    }
+
+    Ok((
+        api_key.id,
+        Viewer::User {
+            id: user.id,
+            role: user.role,
+        },
+    ))
+}
+
+// 依路由樣板與 HTTP 方法決定 API key 需要的 scope
+// GET / HEAD 需要 read，其餘需要 write；/api-keys 與登入只能以帳號密碼操作，回傳 None
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let resource = if path.starts_with("/api-keys") || path.starts_with("/auth") {
+        return None;
+    } else if path.contains("/comments") {
+        Resource::Comments
+    } else if path.contains("/tags") {
+        Resource::Tags
+    } else if path.starts_with("/users") && !path.ends_with("/posts") {
+        Resource::Users
+    } else {
+        Resource::Posts
+    };
+    let access = if method == Method::GET || method == Method::HEAD {
+        Access::Read
+    } else {
+        Access::Write
+    };
+    Some(Scope { resource, access })
+}
+
+impl Viewer {
+    fn role(self) -> Option<Role> {
+        match self {
+            Viewer::Anonymous => None,
+            Viewer::Service => Some(Role::Editor),
+            Viewer::User { role, .. } => Some(role),
+        }
+    }
+
+    fn user_id(self) -> Option<i32> {
+        match self {
+            Viewer::User { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    fn can(self, permission: Permission) -> bool {
+        self.role().is_some_and(|role| role.grants(permission))
+    }
+
+    fn is_author(self, post: &entity::Model) -> bool {
+        self.user_id().is_some() && post.author_id == self.user_id()
+    }
+
+    /// 寫入操作需要登入
+    fn require_login(self) -> Result<Role, Denied> {
+        self.role().ok_or(Denied::Unauthenticated)
+    }
+
+    /// 文章作者本人可以直接操作，其他人需要 permission
+    fn authorize_post(self, post: &entity::Model, permission: Permission) -> Result<(), Denied> {
+        self.require_login()?.authorize(self.is_author(post), permission)
+    }
+
+    /// 公開的文章所有人都看得到；未公開的只有作者本人與可查看草稿的角色看得到
+    fn can_view(self, post: &entity::Model) -> bool {
+        is_public(post) || self.is_author(post) || self.can(Permission::ReadDrafts)
+    }
+
+    /// `?include_deleted=true` 時已刪除文章的可見條件，與還原的權限相同：
+    /// 擁有 posts:delete 權限時不限制，否則只看得到自己的已刪除文章；匿名讀者不能使用
+    fn deleted_visibility(self) -> Result<Option<Condition>, Denied> {
+        self.require_login()?;
+        if self.can(Permission::DeleteAnyPost) {
+            return Ok(None);
+        }
+        let mut condition = Condition::any().add(PostColumn::DeletedAt.is_null());
+        if let Some(id) = self.user_id() {
+            condition = condition.add(PostColumn::AuthorId.eq(id));
+        }
+        Ok(Some(condition))
+    }
+
+    /// 列表查詢的可見條件，與 can_view 相同；None 代表不需限制
+    fn visibility(self) -> Option<Condition> {
+        if self.can(Permission::ReadDrafts) {
+            return None;
+        }
+        let mut condition = Condition::any().add(publicly_visible());
+        if let Some(id) = self.user_id() {
+            condition = condition.add(PostColumn::AuthorId.eq(id));
+        }
+        Some(condition)
+    }
+}
+
+// 比較 token 時不因第一個不同的字元提早結束，避免以回應時間猜測 token
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct CreatePost {
     title: String,
     text: String,
+    author_id: Option<i32>,
 }
 
-#[derive(Deserialize)]
+/// PUT 為整筆取代 title 與 text
+#[derive(Deserialize, ToSchema)]
 struct UpdatePost {
     title: String,
     text: String,
+    /// 省略代表不變更作者，`null` 代表清除作者
+    #[serde(default)]
+    #[schema(value_type = Option<i32>)]
+    author_id: PatchField<i32>,
+}
+
+/// PATCH 使用的部分更新 Payload，只有出現的欄位會設為 ActiveValue::Set
+// `#[serde(default)]` 寫在欄位上而非 struct 上，OpenAPI 文件才不會把「沒出現」列為預設值
+#[derive(Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchPost {
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    title: PatchField<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    text: PatchField<String>,
+    /// author_id 可為空，`null` 代表清除作者
+    #[serde(default)]
+    #[schema(value_type = Option<i32>)]
+    author_id: PatchField<i32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateUser {
+    username: String,
+    email: String,
+    password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct Login {
+    username: String,
+    password: String,
+}
+
+/// POST /auth/login 的回應
+/// ex07 只簽發短效的 access token，過期後重新登入 (refresh / 登出見 ex06)
+#[derive(Serialize, ToSchema)]
+struct AccessToken {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: u64,
+}
+
+/// POST /api-keys 的 Payload
+/// 例: {"name": "ci", "scopes": ["posts:write"], "expires_at": "2030-01-01T00:00:00Z"}
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateApiKey {
+    name: String,
+    scopes: Vec<String>,
+    /// 省略代表永不過期
+    #[schema(value_type = Option<String>, format = DateTime)]
+    expires_at: Option<DateTimeUtc>,
+}
+
+/// API key 的資訊，`key` 只有在建立時回傳一次
+#[derive(Serialize, ToSchema)]
+struct ApiKeyResponse {
+    id: i32,
+    name: String,
+    prefix: String,
+    scopes: Vec<String>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    expires_at: Option<DateTimeUtc>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    last_used_at: Option<DateTimeUtc>,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: DateTimeUtc,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+impl From<api_key_entity::Model> for ApiKeyResponse {
+    fn from(model: api_key_entity::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            prefix: model.prefix,
+            scopes: Scopes::parse(&model.scopes).unwrap_or_default().to_vec(),
+            expires_at: model.expires_at,
+            last_used_at: model.last_used_at,
+            created_at: model.created_at,
+            key: None,
+        }
+    }
+}
+
+/// PUT /users/{id}/role 的 Payload
+#[derive(Deserialize, ToSchema)]
+struct AssignRole {
+    role: Role,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateComment {
+    body: String,
+    /// 回覆某則留言時帶入，該留言必須屬於同一篇文章
+    parent_id: Option<i32>,
+}
+
+/// 討論串中的留言與其下的回覆
+#[derive(Serialize, ToSchema)]
+struct CommentNode {
+    #[serde(flatten)]
+    comment: comment_entity::Model,
+    #[schema(no_recursion)]
+    replies: Vec<CommentNode>,
+}
+
+/// PUT /posts/{id}/tags 的 Payload，以新的標籤清單取代原本的標籤
+#[derive(Deserialize, ToSchema)]
+struct SetTags {
+    tags: Vec<String>,
+}
+
+/// GET /posts/{id}/revisions/{rev}/diff 的回應
+/// `lines` 為內文逐行比較的結果，由舊版本 (修訂紀錄) 到目前的內容
+#[derive(Serialize, ToSchema)]
+struct RevisionDiff {
+    revision: i32,
+    current_version: i32,
+    /// 標題沒有變更時為 null
+    title: Option<TitleChange>,
+    lines: Vec<DiffLine>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TitleChange {
+    from: String,
+    to: String,
+}
+
+/// 單行的比較結果，行號從 1 開始，新增的行沒有 old_line、刪除的行沒有 new_line
+#[derive(Serialize, ToSchema)]
+struct DiffLine {
+    op: &'static str,
+    old_line: Option<usize>,
+    new_line: Option<usize>,
+    text: String,
+}
+
+/// 列表中的文章，附帶作者資料 (與文章以同一個 JOIN 查詢取得)
+#[derive(Serialize, ToSchema)]
+struct PostWithAuthor {
+    #[serde(flatten)]
+    post: entity::Model,
+    author: Option<user_entity::Model>,
+    /// 只有 `?render=html` 時才會出現
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+}
+
+/// GET /posts 的查詢參數，用法與 ex06 的 GET /users 相同
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListPostsQuery {
+    limit: Option<u64>,
+    offset: Option<u64>,
+    after: Option<i64>,
+    sort: Option<String>,
+    title_contains: Option<String>,
+    text_contains: Option<String>,
+    /// 只列出此作者 (users.id) 的文章
+    author: Option<i32>,
+    /// 以逗號分隔的標籤，只列出同時擁有這些標籤的文章
+    tag: Option<String>,
+    /// 預設不列出已軟刪除的文章；設為 true 需要登入，
+    /// 沒有 posts:delete 權限時只會多列出自己的已刪除文章
+    #[serde(default)]
+    include_deleted: bool,
+    /// 依發佈狀態篩選，匿名讀者只會看到公開的文章
+    status: Option<PostStatus>,
+    render: Option<RenderFormat>,
+}
+
+/// `?render=` 支援的格式，目前只有 html
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum RenderFormat {
+    Html,
+}
+
+impl RenderFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            RenderFormat::Html => "html",
+        }
+    }
+}
+
+/// GET /posts/{id} 的查詢參數
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetPostQuery {
+    render: Option<RenderFormat>,
+}
+
+/// `?render=html` 時回傳的單篇文章，`html` 為清理過的內文 HTML
+#[derive(Serialize, ToSchema)]
+struct RenderedPost {
+    #[serde(flatten)]
+    post: entity::Model,
+    html: String,
+}
+
+/// POST /posts/{id}/publish 的 Payload (可省略)
+/// `publish_at` 晚於現在時為排程發佈，省略時立即發佈
+#[derive(Default, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+struct PublishPost {
+    #[schema(value_type = Option<String>, format = DateTime)]
+    publish_at: Option<DateTimeUtc>,
+}
+
+/// GET /posts/search 的查詢參數
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    q: String,
+    limit: Option<u64>,
+}
+
+/// 全文檢索的單筆結果
+#[derive(Debug, Serialize, FromQueryResult, ToSchema)]
+struct SearchHit {
+    id: i32,
+    title: String,
+    /// 已跳脫的 HTML，符合的字詞以 <mark> 標記
+    title_highlight: String,
+    /// 內文中符合的片段，格式同 title_highlight
+    snippet: String,
+    /// bm25 分數，越小代表越相關
+    rank: f64,
 }
 
 /// 範例 07: 使用 SeaORM 的 CRUD
 /// SeaORM 是 Rust 中最熱門的非同步 ORM，支援 SQLx
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // 日誌格式與層級見 src/telemetry.rs (LOG_FORMAT / RUST_LOG)
+    telemetry::init().map_err(anyhow::Error::msg)?;
+
     // 1. 建立資料庫連線
-    let conn = Database::connect(DB_URL).await?;
-    println!("Database connected: {}", DB_URL);
-
-    // 2. 建立資料表 (Migration)
-    // 實務上通常使用 `sea-orm-cli` 進行 migration
-    // 這裡為了範例方便，使用 Schema Helper 動態建立
-    let builder = conn.get_database_backend();
-    let schema = Schema::new(builder);
-    let create_table_stmt = schema.create_table_from_entity(Post);
-    
-    // 執行 Create Table
-    // 注意: create_table_from_entity 產生的是 TableCreateStatement
-    // 我們需要確保資料表不存在才建立 (sqlite 支援 IF NOT EXISTS)
-    // SeaORM 的 create_table_from_entity 預設不包含 IF NOT EXISTS，需手動處理或直接執行
-    // 這裡簡單嘗試執行，忽略錯誤 (如果已存在)
-    let _ = conn.execute(builder.build(&create_table_stmt)).await;
-    println!("Table schema initialized (if not existed).");
-
-    let state = AppState { conn };
+    // 關閉 sqlx 內建的 SQL 日誌 (會連同參數值一起輸出，包含密碼雜湊)，改由 metric callback 記錄語句與耗時
+    let mut options = ConnectOptions::new(DB_URL);
+    options.sqlx_logging(false);
+    let mut conn = Database::connect(options).await?;
+    conn.set_metric_callback(|info| {
+        telemetry::record_query(&info.statement.sql, info.elapsed, info.failed);
+        metrics::observe_query("sea-orm", &info.statement.sql, info.elapsed, info.failed);
+    });
+    tracing::info!("Database connected: {}", DB_URL);
+
+    // 2. 套用資料庫 Migration (見 src/migrations/posts_db.rs)
+    // 子命令 `migrate up | down [n] | status` 只處理 migration，不啟動伺服器
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match MigrateCommand::from_args(&args).map_err(anyhow::Error::msg)? {
+        Some(MigrateCommand::Up) => {
+            Migrator::up(&conn, None).await?;
+            println!("Database schema is up to date.");
+            return Ok(());
+        }
+        Some(MigrateCommand::Down(steps)) => {
+            Migrator::down(&conn, Some(steps)).await?;
+            println!("Rolled back {} migration(s).", steps);
+            return Ok(());
+        }
+        Some(MigrateCommand::Status) => {
+            for migration in Migrator::get_migration_with_status(&conn).await? {
+                println!("{:<32} {}", migration.name(), migration.status());
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+    // Migration 失敗 (磁碟已滿、權限不足等) 會直接中止啟動，而不是帶著壞掉的 schema 開始服務
+    Migrator::up(&conn, None).await?;
+    // 再確認資料表與 Entity 定義一致，避免改了 Model 卻忘了補 migration
+    schema_check::verify_entity(&conn, Post).await?;
+    schema_check::verify_entity(&conn, User).await?;
+    schema_check::verify_entity(&conn, Comment).await?;
+    schema_check::verify_entity(&conn, Tag).await?;
+    schema_check::verify_entity(&conn, PostTag).await?;
+    schema_check::verify_entity(&conn, SlugRedirect).await?;
+    schema_check::verify_entity(&conn, Revision).await?;
+    schema_check::verify_entity(&conn, ApiKey).await?;
+    tracing::info!("Database schema is up to date.");
+
+    let editor_token = std::env::var(EDITOR_TOKEN_ENV).ok().filter(|t| !t.is_empty());
+    if editor_token.is_none() {
+        tracing::warn!("{} is not set, scripts must log in as a user", EDITOR_TOKEN_ENV);
+    }
+    // 未設定金鑰時使用隨機金鑰，重新啟動後所有人都需要重新登入
+    let jwt = match std::env::var(JWT_SECRET_ENV) {
+        Ok(secret) if !secret.is_empty() => JwtKeys::from_secret(secret.as_bytes()),
+        _ => {
+            tracing::warn!("{} is not set, using a random key; tokens will not survive a restart", JWT_SECRET_ENV);
+            JwtKeys::random()
+        }
+    };
+    // 關閉流程見 src/shutdown.rs (SHUTDOWN_DELAY / SHUTDOWN_TIMEOUT)
+    let shutdown = Shutdown::install().map_err(anyhow::Error::msg)?;
+    // CORS、壓縮、請求大小限制、逾時與安全性 header 見 src/http_layers.rs
+    let http_layers = HttpLayers::from_env().map_err(anyhow::Error::msg)?;
+    let state = AppState {
+        conn: conn.clone(),
+        editor_token: editor_token.map(Arc::from),
+        jwt: Arc::new(jwt),
+        render_cache: Arc::new(RenderCache::default()),
+        shutdown: shutdown.clone(),
+    };
+    // 限流設定見 src/rate_limit.rs，登入另外限制以防止暴力猜測密碼
+    let limiter = Arc::new(RateLimiter::from_env()?.with_route("/auth/login", Quota::per_minute(10)));
 
     // 3. 建立路由
-    let app = Router::new()
-        .route("/posts", get(list_posts).post(create_post))
-        .route("/posts/{id}", get(get_post).put(update_post).delete(delete_post))
-        .with_state(state);
+    // 路由與 OpenAPI 文件 (GET /openapi.json、GET /docs) 見 api_routes
+    let app = api_routes()
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit::limit))
+        // API key 驗證在限流外層：有效的金鑰以金鑰 id 計算額度，其餘請求以 IP 計算，
+        // 無效的金鑰直接回傳 401，換金鑰也無法取得新的額度
+        .route_layer(middleware::from_fn_with_state(state.clone(), api_key_auth))
+        // 加在限流與 API key 檢查之後，Prometheus 抓取指標與健康檢查不受影響
+        .routes(routes!(get_metrics))
+        .routes(routes!(health::healthz))
+        .routes(routes!(readyz));
+    let app = openapi::finish(app).with_state(state);
+    let app = http_layers
+        .apply(app)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(telemetry::trace_request));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("SeaORM API Server running at http://{}", addr);
+    tracing::info!("SeaORM API Server running at http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // 帶上連線資訊，限流才能以來源 IP 區分 client
+    // 收到 SIGINT / SIGTERM 後等處理中的請求完成，避免寫入到一半被中斷
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone().stop_accepting());
+    shutdown.drain(server).await?;
+
+    // 等連線都歸還後關閉連線池
+    conn.close().await?;
+    tracing::info!("Database connections closed, server stopped");
 
     Ok(())
 }
 
+/// 文章、留言、標籤、使用者與 API key 的路由，各路由的說明寫在 handler 的 `#[utoipa::path]`
+/// 讀取不需要登入，但匿名讀者只看得到公開的文章；帶 API key 時所有路由都受 scope 限制
+fn api_routes() -> OpenApiRouter<AppState> {
+    OpenApiRouter::with_openapi(ApiDoc::openapi())
+        .routes(routes!(list_posts, create_post))
+        .routes(routes!(search_posts))
+        .routes(routes!(get_post_by_slug))
+        .routes(routes!(get_post, update_post, patch_post, delete_post))
+        .routes(routes!(restore_post))
+        .routes(routes!(publish_post))
+        .routes(routes!(archive_post))
+        .routes(routes!(list_revisions))
+        .routes(routes!(revision_diff))
+        .routes(routes!(restore_revision))
+        .routes(routes!(list_comments, create_comment))
+        .routes(routes!(list_tags, set_tags))
+        .routes(routes!(create_user))
+        .routes(routes!(get_user))
+        .routes(routes!(assign_role))
+        .routes(routes!(list_user_posts))
+        .routes(routes!(login))
+        .routes(routes!(list_api_keys, create_api_key))
+        .routes(routes!(revoke_api_key))
+}
+
+/// OpenAPI 文件的標題、說明與驗證方式
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Posts API",
+        description = "範例 07: SeaORM CRUD，包含發佈流程、修訂紀錄、留言、標籤與全文檢索",
+    ),
+    modifiers(&BEARER_AUTH, &API_KEY_AUTH),
+)]
+struct ApiDoc;
+
+const BEARER_AUTH: BearerAuth = BearerAuth("POST /auth/login 取得的 access token，或 POSTS_EDITOR_TOKEN");
+const API_KEY_AUTH: ApiKeyAuth = ApiKeyAuth {
+    header: "X-API-Key",
+    description: "POST /api-keys 建立的金鑰 (pk_...)，只能使用金鑰 scopes 內的權限",
+};
+
+/// 將使用者輸入轉為安全的 FTS5 查詢
+/// 每個字詞都以雙引號包起來當作字串比對，避免 `"`、`*`、`NEAR` 等語法造成查詢錯誤
+/// 多個字詞以空白分隔，代表 AND
+fn fts_query(input: &str) -> Result<String, String> {
+    let terms: Vec<&str> = input.split_whitespace().collect();
+    if terms.is_empty() {
+        return Err("Query `q` must not be empty".to_string());
+    }
+    if let Some(short) = terms.iter().find(|t| t.chars().count() < 3) {
+        return Err(format!("Search term `{}` is too short (minimum 3 characters)", short));
+    }
+    Ok(terms
+        .iter()
+        .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// 標籤一律去除前後空白並轉為小寫，`rust` 與 `Rust` 視為同一個標籤
+/// 逗號在列表篩選中作為分隔符號，因此不能出現在標籤名稱中
+fn normalize_tag(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err("Tag name must not be empty".to_string());
+    }
+    if name.contains(',') {
+        return Err(format!("Tag name `{}` must not contain `,`", name));
+    }
+    if name.chars().count() > 50 {
+        return Err(format!("Tag name `{}` is too long (maximum 50 characters)", name));
+    }
+    Ok(name)
+}
+
+/// 將同一篇文章的留言 (依 id 排序) 組成樹狀討論串
+fn build_thread(comments: Vec<comment_entity::Model>) -> Vec<CommentNode> {
+    let mut children: HashMap<Option<i32>, Vec<comment_entity::Model>> = HashMap::new();
+    for comment in comments {
+        children.entry(comment.parent_id).or_default().push(comment);
+    }
+
+    fn attach(
+        parent_id: Option<i32>,
+        children: &mut HashMap<Option<i32>, Vec<comment_entity::Model>>,
+    ) -> Vec<CommentNode> {
+        children
+            .remove(&parent_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|comment| {
+                let replies = attach(Some(comment.id), children);
+                CommentNode { comment, replies }
+            })
+            .collect()
+    }
+    attach(None, &mut children)
+}
+
+/// 匿名讀者可見的條件：已發佈且發佈時間已到 (排程中的文章尚未公開)
+/// 以 julianday 比較時間，不受時間字串格式 (`Z` 或 `+00:00`、小數位數) 影響
+fn publicly_visible() -> Condition {
+    Condition::all()
+        .add(PostColumn::Status.eq(PostStatus::Published))
+        .add(Expr::cust("julianday(posts.published_at) <= julianday('now')"))
+}
+
+// 與 publicly_visible 相同的判斷，用於已查出的單筆文章
+fn is_public(post: &entity::Model) -> bool {
+    post.status == PostStatus::Published && post.published_at.is_some_and(|t| t <= chrono::Utc::now())
+}
+
 // --- Handlers ---
 
-/// 列出所有文章
-async fn list_posts(State(state): State<AppState>) -> impl IntoResponse {
-    // 使用 Entity::find() 查詢所有
-    let posts = Post::find().all(&state.conn).await;
+/// 檢查資料庫可以連線、schema 已套用所有 migration
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "ops",
+    summary = "資料庫可以連線且 schema 為最新",
+    responses(
+        (status = 200, description = "可以接受請求", body = health::Readiness),
+        (status = 503, description = "有依賴無法使用，或正在關閉", body = health::Readiness),
+    ),
+)]
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let conn = &state.conn;
+    let (database, schema) = tokio::join!(
+        health::check(async {
+            let select = Statement::from_string(conn.get_database_backend(), "SELECT 1");
+            conn.query_one(select).await.map(drop).map_err(|e| e.to_string())
+        }),
+        health::check(async {
+            let pending = Migrator::get_pending_migrations(conn).await.map_err(|e| e.to_string())?;
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let names: Vec<&str> = pending.iter().map(|m| m.name()).collect();
+            Err(format!("pending migrations: {}", names.join(", ")))
+        }),
+    );
+    health::readiness(state.shutdown.is_draining(), vec![("database", database), ("migrations", schema)])
+}
+
+/// Prometheus 指標，附上連線池目前的使用狀況
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "ops",
+    summary = "Prometheus 指標",
+    responses(
+        (status = 200, description = "Prometheus text format"),
+    ),
+)]
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let pool = state.conn.get_sqlite_connection_pool();
+    metrics::render(&[metrics::PoolStats {
+        backend: "sea-orm",
+        max: pool.options().get_max_connections(),
+        open: pool.size(),
+        idle: u32::try_from(pool.num_idle()).unwrap_or(u32::MAX),
+    }])
+}
+
+/// 列出文章，支援分頁、排序與篩選，每篇文章附帶作者資料
+/// 例: GET /posts?limit=10&sort=-id&title_contains=rust&author=1&render=html
+#[utoipa::path(
+    get,
+    path = "/posts",
+    tag = "posts",
+    summary = "取得文章列表，支援分頁、排序與篩選，附帶作者資料",
+    params(
+        ListPostsQuery,
+    ),
+    responses(
+        (status = 200, description = "符合條件的文章", body = Page<PostWithAuthor>),
+        (status = 400, description = "排序欄位、游標或篩選條件不合法"),
+        (status = 401, description = "include_deleted=true 但沒有登入"),
+    ),
+)]
+async fn list_posts(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(query): Query<ListPostsQuery>,
+) -> impl IntoResponse {
+    let sort = match parse_sort(query.sort.as_deref(), &["id", "title"]) {
+        Ok(s) => s,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let cursor_descending = cursor_direction(&sort);
+    if query.after.is_some() && cursor_descending.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "`after` can only be combined with sort=id or sort=-id",
+        )
+            .into_response();
+    }
+    let limit = effective_limit(query.limit);
+    let offset = query.offset.unwrap_or(0);
+
+    // 使用 Entity::find() 建立查詢，再逐步加上篩選條件
+    let mut select = Post::find();
+    if query.include_deleted {
+        match viewer.deleted_visibility() {
+            Ok(Some(condition)) => select = select.filter(condition),
+            Ok(None) => {}
+            Err(denied) => return denied.into_response(),
+        }
+    } else {
+        select = select.filter(PostColumn::DeletedAt.is_null());
+    }
+    if let Some(visibility) = viewer.visibility() {
+        select = select.filter(visibility);
+    }
+    if let Some(status) = query.status {
+        select = select.filter(PostColumn::Status.eq(status));
+    }
+    if let Some(needle) = &query.title_contains {
+        select = select.filter(
+            Expr::col((Post, PostColumn::Title)).like(LikeExpr::new(contains_pattern(needle)).escape('\\')),
+        );
+    }
+    if let Some(needle) = &query.text_contains {
+        select = select.filter(
+            Expr::col((Post, PostColumn::Text)).like(LikeExpr::new(contains_pattern(needle)).escape('\\')),
+        );
+    }
+
+    if let Some(author) = query.author {
+        select = select.filter(PostColumn::AuthorId.eq(author));
+    }
+    // 每個標籤各自一個子查詢 (post_tags JOIN tags)，文章必須同時符合全部條件
+    for name in query.tag.as_deref().unwrap_or_default().split(',').filter(|t| !t.trim().is_empty()) {
+        let name = match normalize_tag(name) {
+            Ok(n) => n,
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        };
+        let tagged_posts = PostTag::find()
+            .select_only()
+            .column(PostTagColumn::PostId)
+            .inner_join(Tag)
+            .filter(TagColumn::Name.eq(name))
+            .into_query();
+        select = select.filter(PostColumn::Id.in_subquery(tagged_posts));
+    }
+
+    // total 只套用篩選條件，不受游標與分頁影響
+    let total = match select.clone().count(&state.conn).await {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    if let Some(after) = query.after {
+        select = if cursor_descending == Some(true) {
+            select.filter(PostColumn::Id.lt(after))
+        } else {
+            select.filter(PostColumn::Id.gt(after))
+        };
+    }
+    for key in &sort {
+        let column = match key.field {
+            "title" => PostColumn::Title,
+            _ => PostColumn::Id,
+        };
+        select = select.order_by(column, if key.descending { Order::Desc } else { Order::Asc });
+    }
+    // 以 id 作為次要排序確保結果穩定
+    if !sort.iter().any(|k| k.field == "id") {
+        select = select.order_by_asc(PostColumn::Id);
+    }
+
+    // 多取一筆用來判斷是否還有下一頁
+    // find_also_related 以 LEFT JOIN users 一次取回作者，不必每篇文章再各查一次
+    let posts = select
+        .find_also_related(User)
+        .limit(limit + 1)
+        .offset(offset)
+        .all(&state.conn)
+        .await;
 
     match posts {
-        Ok(posts) => (StatusCode::OK, Json(posts)).into_response(),
+        Ok(mut posts) => {
+            let has_more = posts.len() as u64 > limit;
+            posts.truncate(limit as usize);
+            let next_cursor = match (has_more, cursor_descending) {
+                (true, Some(_)) => posts.last().map(|(p, _)| p.id as i64),
+                _ => None,
+            };
+            let items = posts
+                .into_iter()
+                .map(|(post, author)| {
+                    let html = query
+                        .render
+                        .map(|_| state.render_cache.get_or_render(post.id, post.version, &post.text).to_string());
+                    PostWithAuthor { post, author, html }
+                })
+                .collect();
+            let page = Page {
+                items,
+                total,
+                limit,
+                offset,
+                next_cursor,
+            };
+            (StatusCode::OK, Json(page)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 全文檢索文章，依相關度排序並回傳標示過的摘要
+/// 例: GET /posts/search?q=SeaORM
+#[utoipa::path(
+    get,
+    path = "/posts/search",
+    tag = "posts",
+    summary = "全文檢索標題與內文",
+    params(
+        SearchQuery,
+    ),
+    responses(
+        (status = 200, description = "依相關程度排序的結果", body = Vec<SearchHit>),
+        (status = 400, description = "查詢字串為空或字詞太短"),
+    ),
+)]
+async fn search_posts(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(query): Query<SearchQuery>,
+) -> impl IntoResponse {
+    let fts = match fts_query(&query.q) {
+        Ok(q) => q,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let limit = effective_limit(query.limit);
+    let hits = find_search_hits(&state.conn, fts, &viewer, limit).await;
+
+    match hits {
+        Ok(hits) => (StatusCode::OK, Json(hits)).into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
+// FTS 標記符合字詞用的字元 (Unicode 私人使用區，不會出現在一般文字中)，跳脫 HTML 後才換成 <mark>
+const MARK_START: char = '\u{E000}';
+const MARK_END: char = '\u{E001}';
+
+// 依相關度查詢 viewer 看得到的文章，title_highlight 與 snippet 轉為安全的 HTML
+async fn find_search_hits(
+    conn: &DatabaseConnection,
+    fts: String,
+    viewer: &Viewer,
+    limit: u64,
+) -> Result<Vec<SearchHit>, DbErr> {
+    let stmt = Statement::from_sql_and_values(
+        conn.get_database_backend(),
+        "SELECT p.id, p.title,
+                highlight(posts_fts, 0, '\u{E000}', '\u{E001}') AS title_highlight,
+                snippet(posts_fts, 1, '\u{E000}', '\u{E001}', '…', 16) AS snippet,
+                bm25(posts_fts) AS rank
+         FROM posts_fts
+         JOIN posts p ON p.id = posts_fts.rowid
+         WHERE posts_fts MATCH ? AND p.deleted_at IS NULL
+           AND (? OR p.author_id = ? OR (p.status = 'published' AND julianday(p.published_at) <= julianday('now')))
+         ORDER BY rank
+         LIMIT ?",
+        [
+            fts.into(),
+            viewer.can(Permission::ReadDrafts).into(),
+            viewer.user_id().into(),
+            (limit as i64).into(),
+        ],
+    );
+    let mut hits = SearchHit::find_by_statement(stmt).all(conn).await?;
+    for hit in &mut hits {
+        hit.title_highlight = marked_html(&hit.title_highlight);
+        hit.snippet = marked_html(&hit.snippet);
+    }
+    Ok(hits)
+}
+
+// 跳脫文章內容中的 HTML，再將 MARK_START / MARK_END 換成 <mark> / </mark>
+fn marked_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+// 回傳單一文章並附上 ETag，讓客戶端後續可用 If-Match 更新
+fn post_response(status: StatusCode, post: entity::Model) -> Response {
+    (status, [(header::ETAG, etag(post.version.into()))], Json(post)).into_response()
+}
+
+// 將 SeaORM 錯誤轉為 HTTP 回應，唯一限制衝突回傳 409 並指出衝突欄位
+fn db_error_response(err: DbErr) -> Response {
+    match err.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(msg)) => {
+            let field = [("users.username", "username"), ("users.email", "email"), ("posts.slug", "slug")]
+                .into_iter()
+                .find(|(column, _)| msg.contains(column))
+                .map_or("value", |(_, field)| field);
+            (StatusCode::CONFLICT, format!("{} already exists", field)).into_response()
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// 確認 author_id 指向存在的使用者，讓客戶端得到 400 而不是外鍵錯誤的 500
+async fn check_author(conn: &DatabaseConnection, author_id: Option<i32>) -> Result<(), Response> {
+    let Some(author_id) = author_id else {
+        return Ok(());
+    };
+    match User::find_by_id(author_id).one(conn).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("author_id {} does not reference an existing user", author_id),
+        )
+            .into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+// 修改文章前的權限檢查：作者本人或擁有 posts:update 權限
+// `new_author` 為 Some 且與目前作者不同時代表要變更作者，作者本人也需要 posts:update
+fn authorize_post_update(
+    viewer: Viewer,
+    post: &entity::Model,
+    new_author: Option<Option<i32>>,
+) -> Result<(), Denied> {
+    viewer.authorize_post(post, Permission::UpdateAnyPost)?;
+    match new_author {
+        Some(author_id) if author_id != post.author_id && !viewer.can(Permission::UpdateAnyPost) => {
+            Err(Denied::Forbidden(Permission::UpdateAnyPost))
+        }
+        _ => Ok(()),
+    }
+}
+
+// 寫入時以 `version = 目前版本` 為條件並將 version + 1
+// 若期間被其他請求修改，UPDATE 影響 0 筆，SeaORM 回傳 DbErr::RecordNotUpdated
+// Entity::update 不會經過 ActiveModelBehavior，因此手動呼叫 before_save 更新 updated_at 與 slug
+// before_save 可能一併寫入舊 slug 的轉址，與 UPDATE 放在同一個交易中，更新失敗時一起回滾
+async fn update_with_version(
+    conn: &DatabaseConnection,
+    mut active_model: PostActiveModel,
+    current_version: i32,
+) -> Result<entity::Model, DbErr> {
+    active_model.version = ActiveValue::Set(current_version + 1);
+    let txn = conn.begin().await?;
+
+    // title / text 有變更時，先將更新前的內容存成一筆修訂紀錄
+    let previous = Post::find_by_id(active_model.id.clone().unwrap())
+        .filter(PostColumn::Version.eq(current_version))
+        .one(&txn)
+        .await?;
+    if let Some(previous) = previous
+        && content_changed(&active_model, &previous)
+    {
+        let revision = RevisionActiveModel {
+            post_id: ActiveValue::Set(previous.id),
+            version: ActiveValue::Set(previous.version),
+            title: ActiveValue::Set(previous.title),
+            text: ActiveValue::Set(previous.text),
+            created_at: ActiveValue::Set(chrono::Utc::now()),
+            ..Default::default()
+        };
+        revision.insert(&txn).await?;
+    }
+
+    let active_model = active_model.before_save(&txn, false).await?;
+    let post = Post::update(active_model)
+        .filter(PostColumn::Version.eq(current_version))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(post)
+}
+
+// 判斷這次更新是否會改變 title 或 text
+fn content_changed(active_model: &PostActiveModel, previous: &entity::Model) -> bool {
+    matches!(&active_model.title, ActiveValue::Set(title) if *title != previous.title)
+        || matches!(&active_model.text, ActiveValue::Set(text) if *text != previous.text)
+}
+
+// 以版本條件更新並回傳更新後的文章
+async fn save_with_version(
+    conn: &DatabaseConnection,
+    active_model: PostActiveModel,
+    current_version: i32,
+) -> Response {
+    match update_with_version(conn, active_model, current_version).await {
+        Ok(updated_post) => post_response(StatusCode::OK, updated_post),
+        Err(DbErr::RecordNotUpdated) => precondition_failed().into_response(),
+        // 與其他文章同時改成相同標題時，slug 的唯一限制回傳 409
+        Err(e) => db_error_response(e),
+    }
+}
+
 /// 取得單一文章
+/// 若 If-None-Match 與目前 ETag 相同則回傳 304，不重送內容
+/// `?render=html` 時另外回傳 Markdown 內文轉換後的 HTML
+#[utoipa::path(
+    get,
+    path = "/posts/{id}",
+    tag = "posts",
+    summary = "取得單篇文章",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        GetPostQuery,
+        ("If-None-Match" = Option<String>, Header, description = "先前取得的 ETag，內容沒有變更時回傳 304"),
+    ),
+    responses(
+        (status = 200, description = "文章，`?render=html` 時附上 html", body = RenderedPost),
+        (status = 304, description = "內容沒有變更"),
+        (status = 404, description = "Post not found"),
+    ),
+)]
 async fn get_post(
     State(state): State<AppState>,
+    viewer: Viewer,
     Path(id): Path<i32>,
+    Query(query): Query<GetPostQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    let post = Post::find_by_id(id).one(&state.conn).await;
+    let post = Post::find_by_id(id).filter(PostColumn::DeletedAt.is_null()).one(&state.conn).await;
 
     match post {
-        Ok(Some(post)) => (StatusCode::OK, Json(post)).into_response(),
+        Ok(Some(post)) => single_post_response(&state, viewer, post, query.render, &headers),
         Ok(None) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 建立文章
+/// 以 slug 取得文章，例: GET /posts/by-slug/xue-xi-rust
+/// 文章改標題前的舊 slug 會以 301 轉址到目前的 slug
+#[utoipa::path(
+    get,
+    path = "/posts/by-slug/{slug}",
+    tag = "posts",
+    summary = "以 slug 取得文章，舊 slug 轉址到目前的 slug",
+    params(
+        ("slug" = String, Path, description = "文章目前或以前的 slug"),
+        GetPostQuery,
+        ("If-None-Match" = Option<String>, Header, description = "先前取得的 ETag，內容沒有變更時回傳 304"),
+    ),
+    responses(
+        (status = 200, description = "文章，`?render=html` 時附上 html", body = RenderedPost),
+        (status = 301, description = "slug 已變更，Location 為目前的網址"),
+        (status = 304, description = "內容沒有變更"),
+        (status = 404, description = "Post not found"),
+    ),
+)]
+async fn get_post_by_slug(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(slug): Path<String>,
+    Query(query): Query<GetPostQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let post = Post::find()
+        .filter(PostColumn::Slug.eq(&slug))
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(&state.conn)
+        .await;
+
+    match post {
+        Ok(Some(post)) => single_post_response(&state, viewer, post, query.render, &headers),
+        Ok(None) => {
+            let redirect = SlugRedirect::find_by_id(slug).find_also_related(Post).one(&state.conn).await;
+            match redirect {
+                // 轉址前同樣檢查可見性，避免透過舊 slug 得知草稿的新網址
+                Ok(Some((_, Some(post))))
+                    if post.deleted_at.is_none() && viewer.can_view(&post) =>
+                {
+                    let mut location = format!("/posts/by-slug/{}", post.slug);
+                    if query.render.is_some() {
+                        location.push_str("?render=html");
+                    }
+                    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, location)]).into_response()
+                }
+                Ok(_) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// 單篇文章的共用回應：檢查可見性與 If-None-Match，並依 `render` 附上 HTML
+// 看不到的文章 (別人的草稿、排程中、已封存) 一律回傳 404
+fn single_post_response(
+    state: &AppState,
+    viewer: Viewer,
+    post: entity::Model,
+    render: Option<RenderFormat>,
+    headers: &HeaderMap,
+) -> Response {
+    if !viewer.can_view(&post) {
+        return (StatusCode::NOT_FOUND, "Post not found").into_response();
+    }
+    // Markdown 與 HTML 是同一版本的不同表示法，ETag 需要分開
+    let version = post.version.into();
+    let (tag, cached) = match render {
+        Some(format) => (
+            variant_etag(version, format.as_str()),
+            variant_not_modified(headers, version, format.as_str()),
+        ),
+        None => (etag(version), not_modified(headers, version)),
+    };
+    if cached {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, tag)]).into_response();
+    }
+    match render {
+        Some(RenderFormat::Html) => {
+            let html = state.render_cache.get_or_render(post.id, post.version, &post.text);
+            let body = RenderedPost {
+                post,
+                html: html.to_string(),
+            };
+            (StatusCode::OK, [(header::ETAG, tag)], Json(body)).into_response()
+        }
+        None => post_response(StatusCode::OK, post),
+    }
+}
+
+/// 建立文章，需要登入
+/// 未指定 author_id 時作者為自己；以別人的名義建立需要 posts:update 權限
+#[utoipa::path(
+    post,
+    path = "/posts",
+    tag = "posts",
+    summary = "建立文章，以別人的名義建立需要 posts:update",
+    request_body = CreatePost,
+    responses(
+        (status = 201, description = "建立的文章 (草稿)", body = entity::Model),
+        (status = 400, description = "作者不存在"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
 async fn create_post(
     State(state): State<AppState>,
+    viewer: Viewer,
     Json(payload): Json<CreatePost>,
 ) -> impl IntoResponse {
+    let role = match viewer.require_login() {
+        Ok(role) => role,
+        Err(denied) => return denied.into_response(),
+    };
+    let author_id = payload.author_id.or(viewer.user_id());
+    if let Err(denied) = role.authorize(author_id == viewer.user_id(), Permission::UpdateAnyPost) {
+        return denied.into_response();
+    }
+    if let Err(response) = check_author(&state.conn, author_id).await {
+        return response;
+    }
+
     // 建立 ActiveModel
     let new_post = PostActiveModel {
         title: ActiveValue::Set(payload.title),
         text: ActiveValue::Set(payload.text),
+        version: ActiveValue::Set(1),
+        author_id: ActiveValue::Set(author_id),
+        // 新文章一律從草稿開始，需經 POST /posts/{id}/publish 才會公開
+        status: ActiveValue::Set(PostStatus::Draft),
+        published_at: ActiveValue::Set(None),
         ..Default::default() // ID 會自動生成 (NotSet)
     };
 
-    let result = new_post.insert(&state.conn).await;
+    // slug 在 before_save 中決定，同標題的文章同時建立時可能選到同一個 slug，
+    // 較晚寫入的一方違反唯一限制，重新產生 slug (此時已看得到對方的 slug) 再試
+    let mut attempts = 1;
+    let result = loop {
+        match new_post.clone().insert(&state.conn).await {
+            Err(e) if attempts < SLUG_INSERT_ATTEMPTS && is_slug_conflict(&e) => attempts += 1,
+            result => break result,
+        }
+    };
 
     match result {
-        Ok(post) => (StatusCode::CREATED, Json(post)).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Ok(post) => post_response(StatusCode::CREATED, post),
+        Err(e) => db_error_response(e),
     }
 }
 
+// 違反 posts.slug 的唯一限制
+fn is_slug_conflict(err: &DbErr) -> bool {
+    matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(msg)) if msg.contains("posts.slug"))
+}
+
 /// 更新文章
+/// 必須帶 If-Match，避免兩個客戶端同時更新時互相覆蓋
+/// 作者本人或擁有 posts:update 權限者才能修改，變更作者一律需要 posts:update
+#[utoipa::path(
+    put,
+    path = "/posts/{id}",
+    tag = "posts",
+    summary = "整筆更新文章 (作者本人或 posts:update)",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = UpdatePost,
+    responses(
+        (status = 200, description = "更新後的文章", body = entity::Model),
+        (status = 400, description = "作者不存在"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post not found"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
 async fn update_post(
     State(state): State<AppState>,
+    viewer: Viewer,
     Path(id): Path<i32>,
+    headers: HeaderMap,
     Json(payload): Json<UpdatePost>,
 ) -> impl IntoResponse {
-    // 先查詢是否存在
-    let post = Post::find_by_id(id).one(&state.conn).await;
+    let author_id = payload.author_id.nullable();
+
+    // 先查詢是否存在，看不到的草稿與不存在相同，不透露它的存在
+    let post = Post::find_by_id(id).filter(PostColumn::DeletedAt.is_null()).one(&state.conn).await;
 
     match post {
-        Ok(Some(post_model)) => {
+        Ok(Some(post_model)) if viewer.can_view(&post_model) => {
+            if let Err(denied) = authorize_post_update(viewer, &post_model, author_id) {
+                return denied.into_response();
+            }
+            // 通過權限檢查後才確認作者是否存在，未授權的請求無法藉此探測使用者 id
+            if let Some(author_id) = author_id
+                && let Err(response) = check_author(&state.conn, author_id).await
+            {
+                return response;
+            }
+            if let Err(rejection) = check_if_match(&headers, post_model.version.into()) {
+                return rejection.into_response();
+            }
+            let current_version = post_model.version;
+
             // 轉換為 ActiveModel 進行修改
             let mut active_model: PostActiveModel = post_model.into();
             active_model.title = ActiveValue::Set(payload.title);
             active_model.text = ActiveValue::Set(payload.text);
+            if let Some(author_id) = author_id {
+                active_model.author_id = ActiveValue::Set(author_id);
+            }
 
-            let result = active_model.update(&state.conn).await;
-            match result {
-                Ok(updated_post) => (StatusCode::OK, Json(updated_post)).into_response(),
-                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            save_with_version(&state.conn, active_model, current_version).await
+        }
+        Ok(_) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 部分更新文章 (JSON Merge Patch)
+/// 例: PATCH /posts/1 {"title": "新標題"} 只會更新 title
+/// 權限規則與 PUT 相同
+#[utoipa::path(
+    patch,
+    path = "/posts/{id}",
+    tag = "posts",
+    summary = "部分更新文章 (JSON Merge Patch)",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = PatchPost,
+    responses(
+        (status = 200, description = "更新後的文章", body = entity::Model),
+        (status = 400, description = "欄位不可為 null 或作者不存在"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post not found"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn patch_post(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(payload): Json<PatchPost>,
+) -> impl IntoResponse {
+    // title / text 都是 NOT NULL，不允許以 null 清除
+    let (title, text) = match (payload.title.required("title"), payload.text.required("text")) {
+        (Ok(t), Ok(x)) => (t, x),
+        (Err(msg), _) | (_, Err(msg)) => return (StatusCode::BAD_REQUEST, msg).into_response(),
+    };
+    // Missing 為 None (不修改)，null 為 Some(None) (清除作者)
+    let author_id = payload.author_id.nullable();
+
+    let post = Post::find_by_id(id).filter(PostColumn::DeletedAt.is_null()).one(&state.conn).await;
+
+    match post {
+        Ok(Some(post_model)) if viewer.can_view(&post_model) => {
+            if let Err(denied) = authorize_post_update(viewer, &post_model, author_id) {
+                return denied.into_response();
             }
+            if let Some(author_id) = author_id
+                && let Err(response) = check_author(&state.conn, author_id).await
+            {
+                return response;
+            }
+            if let Err(rejection) = check_if_match(&headers, post_model.version.into()) {
+                return rejection.into_response();
+            }
+            if title.is_none() && text.is_none() && author_id.is_none() {
+                return post_response(StatusCode::OK, post_model);
+            }
+            let current_version = post_model.version;
+
+            // 未提供的欄位維持 Unchanged，UPDATE 只會寫入有 Set 的欄位
+            let mut active_model: PostActiveModel = post_model.into();
+            if let Some(title) = title {
+                active_model.title = ActiveValue::Set(title);
+            }
+            if let Some(text) = text {
+                active_model.text = ActiveValue::Set(text);
+            }
+            if let Some(author_id) = author_id {
+                active_model.author_id = ActiveValue::Set(author_id);
+            }
+
+            save_with_version(&state.conn, active_model, current_version).await
         }
-        Ok(None) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Ok(_) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// 刪除文章
+/// 刪除文章 (軟刪除)
+/// 只設定 deleted_at，留言與標籤都保留，可透過 POST /posts/{id}/restore 還原
+/// 作者本人或擁有 posts:delete 權限者才能刪除
+#[utoipa::path(
+    delete,
+    path = "/posts/{id}",
+    tag = "posts",
+    summary = "軟刪除文章 (作者本人或 posts:delete)",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "Post deleted"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:delete"),
+        (status = 404, description = "Post not found"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
 async fn delete_post(
     State(state): State<AppState>,
+    viewer: Viewer,
     Path(id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    let result = Post::delete_by_id(id).exec(&state.conn).await;
+    let post = match Post::find_by_id(id).filter(PostColumn::DeletedAt.is_null()).one(&state.conn).await {
+        Ok(Some(post)) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(denied) = viewer.authorize_post(&post, Permission::DeleteAnyPost) {
+        return denied.into_response();
+    }
+    if let Err(rejection) = check_if_match(&headers, post.version.into()) {
+        return rejection.into_response();
+    }
 
-    match result {
-        Ok(delete_result) => {
-            if delete_result.rows_affected == 0 {
-                 (StatusCode::NOT_FOUND, "Post not found").into_response()
-            } else {
-                 (StatusCode::OK, "Post deleted").into_response()
+    let current_version = post.version;
+    let mut active_model: PostActiveModel = post.into();
+    active_model.deleted_at = ActiveValue::Set(Some(chrono::Utc::now()));
+
+    match update_with_version(&state.conn, active_model, current_version).await {
+        Ok(_) => (StatusCode::OK, "Post deleted").into_response(),
+        Err(DbErr::RecordNotUpdated) => precondition_failed().into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 還原已軟刪除的文章
+/// 與刪除相同需要帶 If-Match (刪除時 version 已 + 1，需以 GET /posts?include_deleted=true 取得，
+/// 作者本人看得到自己的已刪除文章，其他人需要 posts:delete 權限)
+/// 權限規則與刪除相同
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/restore",
+    tag = "posts",
+    summary = "還原已刪除的文章 (作者本人或 posts:delete)",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "還原後的文章", body = entity::Model),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:delete"),
+        (status = 404, description = "Deleted post not found"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn restore_post(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let post = Post::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_not_null())
+        .one(&state.conn)
+        .await;
+    let post = match post {
+        Ok(Some(post)) => post,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Deleted post not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(denied) = viewer.authorize_post(&post, Permission::DeleteAnyPost) {
+        return denied.into_response();
+    }
+    if let Err(rejection) = check_if_match(&headers, post.version.into()) {
+        return rejection.into_response();
+    }
+
+    let current_version = post.version;
+    let mut active_model: PostActiveModel = post.into();
+    active_model.deleted_at = ActiveValue::Set(None);
+
+    save_with_version(&state.conn, active_model, current_version).await
+}
+
+// 將文章轉換為下一個發佈狀態，轉換規則見 PostStatus::can_transition_to
+// 與修改文章相同，需要是作者本人或擁有 posts:update 權限
+async fn transition_post(
+    conn: &DatabaseConnection,
+    viewer: Viewer,
+    id: i32,
+    headers: &HeaderMap,
+    next: PostStatus,
+    publish_at: Option<DateTimeUtc>,
+) -> Response {
+    let post = match find_post(conn, id).await {
+        Ok(post) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    };
+    if let Err(denied) = viewer.authorize_post(&post, Permission::UpdateAnyPost) {
+        return denied.into_response();
+    }
+    if let Err(rejection) = check_if_match(headers, post.version.into()) {
+        return rejection.into_response();
+    }
+    if !post.status.can_transition_to(next) {
+        return (
+            StatusCode::CONFLICT,
+            format!(
+                "Cannot change post status from {} to {}",
+                post.status.to_value(),
+                next.to_value()
+            ),
+        )
+            .into_response();
+    }
+
+    let current_version = post.version;
+    let mut active_model: PostActiveModel = post.into();
+    active_model.status = ActiveValue::Set(next);
+    if next == PostStatus::Published {
+        active_model.published_at = ActiveValue::Set(Some(publish_at.unwrap_or_else(chrono::Utc::now)));
+    }
+    save_with_version(conn, active_model, current_version).await
+}
+
+/// 發佈草稿，例: POST /posts/1/publish {"publish_at": "2030-01-01T00:00:00Z"}
+/// 不帶 Payload 時立即發佈；指定未來時間則在該時間之前不會對匿名讀者公開
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/publish",
+    tag = "posts",
+    summary = "發佈草稿，可指定未來的 publish_at 排程發佈",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = Option<PublishPost>,
+    responses(
+        (status = 200, description = "發佈後的文章", body = entity::Model),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post not found"),
+        (status = 409, description = "目前的狀態不能轉換為 published"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn publish_post(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    payload: Option<Json<PublishPost>>,
+) -> impl IntoResponse {
+    let Json(payload) = payload.unwrap_or_default();
+    transition_post(&state.conn, viewer, id, &headers, PostStatus::Published, payload.publish_at).await
+}
+
+/// 封存已發佈的文章，封存後不再對匿名讀者公開
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/archive",
+    tag = "posts",
+    summary = "封存已發佈的文章",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "封存後的文章", body = entity::Model),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post not found"),
+        (status = 409, description = "目前的狀態不能轉換為 archived"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn archive_post(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    transition_post(&state.conn, viewer, id, &headers, PostStatus::Archived, None).await
+}
+
+/// 列出文章的修訂紀錄，新的在前
+#[utoipa::path(
+    get,
+    path = "/posts/{id}/revisions",
+    tag = "revisions",
+    summary = "列出文章的修訂紀錄，新的在前",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+    ),
+    responses(
+        (status = 200, description = "修訂紀錄", body = Vec<revision_entity::Model>),
+        (status = 404, description = "Post not found"),
+    ),
+)]
+async fn list_revisions(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let post = match find_post(&state.conn, id).await {
+        Ok(post) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    };
+
+    let revisions = post
+        .find_related(Revision)
+        .order_by_desc(RevisionColumn::Version)
+        .all(&state.conn)
+        .await;
+
+    match revisions {
+        Ok(revisions) => (StatusCode::OK, Json(revisions)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// 取得文章與指定版本的修訂紀錄，任一不存在時回傳 404
+async fn find_revision(
+    conn: &DatabaseConnection,
+    post_id: i32,
+    rev: i32,
+) -> Result<(entity::Model, revision_entity::Model), Response> {
+    let post = find_post(conn, post_id).await?;
+    let revision = Revision::find()
+        .filter(RevisionColumn::PostId.eq(post_id))
+        .filter(RevisionColumn::Version.eq(rev))
+        .one(conn)
+        .await;
+    match revision {
+        Ok(Some(revision)) => Ok((post, revision)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Revision not found").into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// 比較修訂紀錄與目前內容的差異 (內文逐行比較)
+/// 例: GET /posts/1/revisions/2/diff
+#[utoipa::path(
+    get,
+    path = "/posts/{id}/revisions/{rev}/diff",
+    tag = "revisions",
+    summary = "比較修訂版本與目前的內容",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("rev" = i32, Path, description = "文章版本號 (修訂紀錄的 version，不是 id)"),
+    ),
+    responses(
+        (status = 200, description = "逐行比較的結果", body = RevisionDiff),
+        (status = 404, description = "Post 或 revision 不存在"),
+    ),
+)]
+async fn revision_diff(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path((id, rev)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let (post, revision) = match find_revision(&state.conn, id, rev).await {
+        Ok((post, _)) if !viewer.can_view(&post) => {
+            return (StatusCode::NOT_FOUND, "Post not found").into_response()
+        }
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    // 結尾有無換行不算差異，否則最後一行總是會被當成修改
+    let (old_text, new_text) = (with_final_newline(&revision.text), with_final_newline(&post.text));
+    let diff = TextDiff::from_lines(&old_text, &new_text);
+    let lines = diff
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Delete => "delete",
+                ChangeTag::Insert => "insert",
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect();
+    let title = (revision.title != post.title).then_some(TitleChange {
+        from: revision.title,
+        to: post.title,
+    });
+
+    let body = RevisionDiff {
+        revision: revision.version,
+        current_version: post.version,
+        title,
+        lines,
+    };
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+fn with_final_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+/// 將文章內容還原為指定的修訂版本
+/// 還原本身也是一次更新：目前的內容會先存成新的修訂紀錄，version + 1，因此需要 If-Match
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/revisions/{rev}/restore",
+    tag = "revisions",
+    summary = "將文章內容還原為指定的修訂版本",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+        ("rev" = i32, Path, description = "文章版本號 (修訂紀錄的 version，不是 id)"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    responses(
+        (status = 200, description = "還原後的文章", body = entity::Model),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post 或 revision 不存在"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn restore_revision(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path((id, rev)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (post, revision) = match find_revision(&state.conn, id, rev).await {
+        Ok((post, _)) if !viewer.can_view(&post) => {
+            return (StatusCode::NOT_FOUND, "Post not found").into_response()
+        }
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if let Err(denied) = viewer.authorize_post(&post, Permission::UpdateAnyPost) {
+        return denied.into_response();
+    }
+    if let Err(rejection) = check_if_match(&headers, post.version.into()) {
+        return rejection.into_response();
+    }
+
+    let current_version = post.version;
+    let mut active_model: PostActiveModel = post.into();
+    active_model.title = ActiveValue::Set(revision.title);
+    active_model.text = ActiveValue::Set(revision.text);
+
+    save_with_version(&state.conn, active_model, current_version).await
+}
+
+// 取得文章，不存在時回傳 404
+async fn find_post(conn: &DatabaseConnection, id: i32) -> Result<entity::Model, Response> {
+    match Post::find_by_id(id).filter(PostColumn::DeletedAt.is_null()).one(conn).await {
+        Ok(Some(post)) => Ok(post),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Post not found").into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// 列出文章的留言，以巢狀的討論串回傳
+/// 所有留言以一次查詢取出後在記憶體中組成樹狀結構
+#[utoipa::path(
+    get,
+    path = "/posts/{id}/comments",
+    tag = "comments",
+    summary = "取得文章的留言，回覆巢狀放在 replies 中",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+    ),
+    responses(
+        (status = 200, description = "留言討論串", body = Vec<CommentNode>),
+        (status = 404, description = "Post not found"),
+    ),
+)]
+async fn list_comments(State(state): State<AppState>, viewer: Viewer, Path(id): Path<i32>) -> impl IntoResponse {
+    let post = match find_post(&state.conn, id).await {
+        Ok(post) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    };
+
+    let comments = post
+        .find_related(Comment)
+        .order_by_asc(CommentColumn::Id)
+        .all(&state.conn)
+        .await;
+
+    match comments {
+        Ok(comments) => (StatusCode::OK, Json(build_thread(comments))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 新增留言，帶 parent_id 時為回覆該則留言
+/// 需要登入，且只能在自己看得到的文章下留言
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/comments",
+    tag = "comments",
+    summary = "新增留言或回覆",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+    ),
+    request_body = CreateComment,
+    responses(
+        (status = 201, description = "建立的留言", body = comment_entity::Model),
+        (status = 400, description = "內容為空，或 parent_id 不屬於這篇文章"),
+        (status = 401, description = "Authentication required"),
+        (status = 404, description = "Post not found"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn create_comment(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    Json(payload): Json<CreateComment>,
+) -> impl IntoResponse {
+    if let Err(denied) = viewer.require_login() {
+        return denied.into_response();
+    }
+    match find_post(&state.conn, id).await {
+        Ok(post) if viewer.can_view(&post) => {}
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    }
+    if payload.body.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "Comment body must not be empty").into_response();
+    }
+    if let Some(parent_id) = payload.parent_id {
+        let parent = Comment::find_by_id(parent_id)
+            .filter(CommentColumn::PostId.eq(id))
+            .one(&state.conn)
+            .await;
+        match parent {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("parent_id {} is not a comment on this post", parent_id),
+                )
+                    .into_response()
             }
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    let new_comment = CommentActiveModel {
+        post_id: ActiveValue::Set(id),
+        parent_id: ActiveValue::Set(payload.parent_id),
+        body: ActiveValue::Set(payload.body),
+        ..Default::default()
+    };
+
+    match new_comment.insert(&state.conn).await {
+        Ok(comment) => (StatusCode::CREATED, Json(comment)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 列出文章的標籤 (經由 post_tags 的多對多查詢)
+/// 與文章本身相同，看不到的文章回傳 404
+#[utoipa::path(
+    get,
+    path = "/posts/{id}/tags",
+    tag = "tags",
+    summary = "列出文章的標籤",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+    ),
+    responses(
+        (status = 200, description = "依名稱排序的標籤", body = Vec<tag_entity::Model>),
+        (status = 404, description = "Post not found"),
+    ),
+)]
+async fn list_tags(State(state): State<AppState>, viewer: Viewer, Path(id): Path<i32>) -> impl IntoResponse {
+    let post = match find_post(&state.conn, id).await {
+        Ok(post) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    };
+
+    match post.find_related(Tag).order_by_asc(TagColumn::Name).all(&state.conn).await {
+        Ok(tags) => (StatusCode::OK, Json(tags)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// 在同一個交易中建立尚不存在的標籤，並以新的清單取代文章原本的標籤
+async fn replace_post_tags(
+    conn: &DatabaseConnection,
+    post_id: i32,
+    names: BTreeSet<String>,
+) -> Result<Vec<tag_entity::Model>, DbErr> {
+    let txn = conn.begin().await?;
+
+    for name in &names {
+        let tag = tag_entity::ActiveModel {
+            name: ActiveValue::Set(name.clone()),
+            ..Default::default()
+        };
+        Tag::insert(tag)
+            .on_conflict(OnConflict::column(TagColumn::Name).do_nothing().to_owned())
+            .exec_without_returning(&txn)
+            .await?;
+    }
+    let tags = Tag::find()
+        .filter(TagColumn::Name.is_in(names))
+        .order_by_asc(TagColumn::Name)
+        .all(&txn)
+        .await?;
+
+    PostTag::delete_many()
+        .filter(PostTagColumn::PostId.eq(post_id))
+        .exec(&txn)
+        .await?;
+    if !tags.is_empty() {
+        let links = tags.iter().map(|tag| post_tag_entity::ActiveModel {
+            post_id: ActiveValue::Set(post_id),
+            tag_id: ActiveValue::Set(tag.id),
+        });
+        PostTag::insert_many(links).exec_without_returning(&txn).await?;
+    }
+
+    txn.commit().await?;
+    Ok(tags)
+}
+
+/// 設定文章的標籤，例: PUT /posts/1/tags {"tags": ["rust", "orm"]}
+/// 回傳設定後的標籤清單，權限規則與修改文章相同
+#[utoipa::path(
+    put,
+    path = "/posts/{id}/tags",
+    tag = "tags",
+    summary = "以新的清單取代文章的標籤 (作者本人或 posts:update)",
+    params(
+        ("id" = i32, Path, description = "文章 id"),
+    ),
+    request_body = SetTags,
+    responses(
+        (status = 200, description = "取代後的標籤", body = Vec<tag_entity::Model>),
+        (status = 400, description = "標籤名稱不合法"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: posts:update"),
+        (status = 404, description = "Post not found"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn set_tags(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    Json(payload): Json<SetTags>,
+) -> impl IntoResponse {
+    let post = match find_post(&state.conn, id).await {
+        Ok(post) if viewer.can_view(&post) => post,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(response) => return response,
+    };
+    if let Err(denied) = viewer.authorize_post(&post, Permission::UpdateAnyPost) {
+        return denied.into_response();
+    }
+    let names = match payload.tags.iter().map(|t| normalize_tag(t)).collect::<Result<BTreeSet<_>, _>>() {
+        Ok(names) => names,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    match replace_post_tags(&state.conn, id, names).await {
+        Ok(tags) => (StatusCode::OK, Json(tags)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 建立使用者 (文章作者)，不需要登入
+/// 新使用者的角色為 reader；系統中還沒有能登入的 admin (設有密碼) 時，第一位註冊的使用者成為 admin
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    summary = "建立使用者，第一位使用者成為 admin",
+    request_body = CreateUser,
+    responses(
+        (status = 201, description = "建立的使用者", body = user_entity::Model),
+        (status = 400, description = "密碼不符合要求"),
+        (status = 409, description = "username 或 email 已存在"),
+    ),
+)]
+async fn create_user(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateUser>,
+) -> impl IntoResponse {
+    if let Err(msg) = validate_password(&payload.password) {
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    // argon2 很耗 CPU，移到 blocking 執行緒計算
+    let password_hash = match tokio::task::spawn_blocking(move || hash_password(&payload.password)).await {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // 角色判斷與插入寫在同一個 INSERT 中，同時註冊的兩位使用者不會都成為 admin
+    let stmt = Statement::from_sql_and_values(
+        state.conn.get_database_backend(),
+        "INSERT INTO users (username, email, password_hash, role)
+         VALUES (?, ?, ?, CASE WHEN EXISTS (SELECT 1 FROM users WHERE role = 'admin' AND password_hash IS NOT NULL)
+                               THEN 'reader' ELSE 'admin' END)
+         RETURNING *",
+        [payload.username.into(), payload.email.into(), password_hash.into()],
+    );
+    match User::find().from_raw_sql(stmt).one(&state.conn).await {
+        Ok(Some(user)) => user_response(StatusCode::CREATED, user),
+        Ok(None) => (StatusCode::INTERNAL_SERVER_ERROR, "Inserted user not returned").into_response(),
+        Err(e) => db_error_response(e),
+    }
+}
+
+/// 取得單一使用者
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    summary = "取得使用者",
+    params(
+        ("id" = i32, Path, description = "使用者 id"),
+    ),
+    responses(
+        (status = 200, description = "使用者", body = user_entity::Model),
+        (status = 404, description = "User not found"),
+    ),
+)]
+async fn get_user(State(state): State<AppState>, Path(id): Path<i32>) -> impl IntoResponse {
+    match User::find_by_id(id).one(&state.conn).await {
+        Ok(Some(user)) => user_response(StatusCode::OK, user),
+        Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// 回傳使用者並附上 ETag，指派角色時以 If-Match 帶回
+fn user_response(status: StatusCode, user: user_entity::Model) -> Response {
+    (status, [(header::ETAG, etag(user.version.into()))], Json(user)).into_response()
+}
+
+/// 以帳號密碼登入，取得 access token
+/// 帳號不存在與密碼錯誤回傳相同的訊息，避免被用來探測帳號
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    summary = "以帳號密碼登入，取得 access token",
+    request_body = Login,
+    responses(
+        (status = 200, description = "access token", body = AccessToken),
+        (status = 401, description = "Invalid username or password"),
+        (status = 429, description = "嘗試次數過多"),
+    ),
+)]
+async fn login(State(state): State<AppState>, Json(payload): Json<Login>) -> impl IntoResponse {
+    let user = match User::find()
+        .filter(UserColumn::Username.eq(&payload.username))
+        .one(&state.conn)
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let Some((user_id, hash)) = user.and_then(|u| u.password_hash.map(|hash| (u.id, hash))) else {
+        return invalid_credentials();
+    };
+    let verified = tokio::task::spawn_blocking(move || verify_password(&payload.password, &hash)).await;
+    if !matches!(verified, Ok(true)) {
+        return invalid_credentials();
+    }
+
+    match state.jwt.issue(user_id.into(), TokenType::Access) {
+        Ok((access_token, _)) => {
+            let body = AccessToken {
+                access_token,
+                token_type: "Bearer",
+                expires_in: auth::ACCESS_TOKEN_TTL.as_secs(),
+            };
+            (StatusCode::OK, Json(body)).into_response()
         }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
+
+fn invalid_credentials() -> Response {
+    unauthorized("Invalid username or password")
+}
+
+/// 指派使用者角色，例: PUT /users/2/role {"role": "editor"}
+/// 需要 users:assign-role 權限；不能拿掉最後一位 admin 的角色
+/// 與 ex06 相同必須帶 If-Match，兩位 admin 同時修改同一位使用者時不會互相覆蓋
+#[utoipa::path(
+    put,
+    path = "/users/{id}/role",
+    tag = "users",
+    summary = "指派角色 (users:assign-role)",
+    params(
+        ("id" = i32, Path, description = "使用者 id"),
+        ("If-Match" = String, Header, description = "先以 GET 取得的 ETag"),
+    ),
+    request_body = AssignRole,
+    responses(
+        (status = 200, description = "更新後的使用者", body = user_entity::Model),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Missing permission: users:assign-role"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Cannot remove the last admin"),
+        (status = 412, description = "資料已被其他請求修改"),
+        (status = 428, description = "缺少 If-Match"),
+    ),
+    security(("bearerAuth" = []), ("apiKeyAuth" = [])),
+)]
+async fn assign_role(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(payload): Json<AssignRole>,
+) -> impl IntoResponse {
+    if let Err(denied) = viewer.require_login().and_then(|role| role.authorize(false, Permission::AssignRole)) {
+        return denied.into_response();
+    }
+    let current = match User::find_by_id(id).one(&state.conn).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(rejection) = check_if_match(&headers, current.version.into()) {
+        return rejection.into_response();
+    }
+    if current.role == payload.role {
+        return user_response(StatusCode::OK, current);
+    }
+
+    // 版本與最後一位 admin 的條件寫在同一個 UPDATE 中，兩位 admin 同時互相降級時不會都成功
+    let result = User::update_many()
+        .col_expr(UserColumn::Role, Expr::value(payload.role))
+        .col_expr(UserColumn::Version, Expr::value(current.version + 1))
+        .filter(UserColumn::Id.eq(id))
+        .filter(UserColumn::Version.eq(current.version))
+        .filter(
+            Condition::any()
+                .add(UserColumn::Role.ne(Role::Admin))
+                .add(Expr::value(payload.role).eq(Role::Admin))
+                .add(Expr::cust_with_values(
+                    "EXISTS (SELECT 1 FROM users AS other
+                             WHERE other.role = 'admin' AND other.password_hash IS NOT NULL AND other.id <> ?)",
+                    [id],
+                )),
+        )
+        .exec(&state.conn)
+        .await;
+
+    let updated = match result {
+        Ok(result) => result.rows_affected > 0,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    match User::find_by_id(id).one(&state.conn).await {
+        Ok(Some(user)) if updated => user_response(StatusCode::OK, user),
+        // 版本沒變代表是最後一位 admin 的條件擋下了更新
+        Ok(Some(user)) if user.version == current.version => {
+            (StatusCode::CONFLICT, "Cannot remove the last admin").into_response()
+        }
+        Ok(_) => precondition_failed().into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 建立 API key，需要以 access token 登入
+/// 金鑰代表自己呼叫 API，權限不會超過自己的角色，只會再受 scopes 限制
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    tag = "api-keys",
+    summary = "建立 API key，金鑰只在這次回應中出現",
+    request_body = CreateApiKey,
+    responses(
+        (status = 201, description = "建立的 API key，包含 key", body = ApiKeyResponse),
+        (status = 400, description = "名稱為空、scope 不合法或 expires_at 已過"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "API keys cannot be used for this endpoint"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn create_api_key(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Json(payload): Json<CreateApiKey>,
+) -> impl IntoResponse {
+    let Some(user_id) = viewer.user_id() else {
+        return Denied::Unauthenticated.into_response();
+    };
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "API key name must not be empty").into_response();
+    }
+    let scopes = match Scopes::parse(&payload.scopes.join(" ")) {
+        Ok(scopes) if scopes != Scopes::default() => scopes,
+        Ok(_) => return (StatusCode::BAD_REQUEST, "At least one scope is required").into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let now = chrono::Utc::now();
+    if payload.expires_at.is_some_and(|t| t <= now) {
+        return (StatusCode::BAD_REQUEST, "expires_at must be in the future").into_response();
+    }
+
+    let generated = api_key::generate();
+    let new_key = ApiKeyActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        name: ActiveValue::Set(name.to_string()),
+        prefix: ActiveValue::Set(generated.prefix),
+        key_hash: ActiveValue::Set(generated.hash),
+        scopes: ActiveValue::Set(scopes.to_vec().join(" ")),
+        expires_at: ActiveValue::Set(payload.expires_at),
+        last_used_at: ActiveValue::Set(None),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    };
+
+    match new_key.insert(&state.conn).await {
+        Ok(model) => {
+            let body = ApiKeyResponse {
+                key: Some(generated.key),
+                ..model.into()
+            };
+            (StatusCode::CREATED, Json(body)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 列出自己的 API key (不含金鑰本身)
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    tag = "api-keys",
+    summary = "列出自己的 API key，不包含金鑰本身",
+    responses(
+        (status = 200, description = "API key", body = Vec<ApiKeyResponse>),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "API keys cannot be used for this endpoint"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn list_api_keys(State(state): State<AppState>, viewer: Viewer) -> impl IntoResponse {
+    let Some(user_id) = viewer.user_id() else {
+        return Denied::Unauthenticated.into_response();
+    };
+    let keys = ApiKey::find()
+        .filter(ApiKeyColumn::UserId.eq(user_id))
+        .order_by_asc(ApiKeyColumn::Id)
+        .all(&state.conn)
+        .await;
+
+    match keys {
+        Ok(keys) => {
+            let body: Vec<ApiKeyResponse> = keys.into_iter().map(Into::into).collect();
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 撤銷 (刪除) 自己的 API key，之後使用該金鑰的請求一律回傳 401
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{id}",
+    tag = "api-keys",
+    summary = "撤銷自己的 API key",
+    params(
+        ("id" = i32, Path, description = "API key id"),
+    ),
+    responses(
+        (status = 204, description = "已撤銷"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "API keys cannot be used for this endpoint"),
+        (status = 404, description = "API key not found"),
+    ),
+    security(("bearerAuth" = [])),
+)]
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let Some(user_id) = viewer.user_id() else {
+        return Denied::Unauthenticated.into_response();
+    };
+    let result = ApiKey::delete_many()
+        .filter(ApiKeyColumn::Id.eq(id))
+        .filter(ApiKeyColumn::UserId.eq(user_id))
+        .exec(&state.conn)
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected == 0 => (StatusCode::NOT_FOUND, "API key not found").into_response(),
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 列出某位使用者的所有文章
+/// 透過 Relation 以 `find_related` 查詢 (WHERE posts.author_id = ?)
+/// 未公開的文章只有作者本人與可查看草稿的角色看得到
+#[utoipa::path(
+    get,
+    path = "/users/{id}/posts",
+    tag = "users",
+    summary = "列出使用者的文章",
+    params(
+        ("id" = i32, Path, description = "使用者 id"),
+    ),
+    responses(
+        (status = 200, description = "使用者的文章", body = Vec<entity::Model>),
+        (status = 404, description = "User not found"),
+    ),
+)]
+async fn list_user_posts(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let user = match User::find_by_id(id).one(&state.conn).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let mut select = user.find_related(Post).filter(PostColumn::DeletedAt.is_null());
+    if let Some(visibility) = viewer.visibility() {
+        select = select.filter(visibility);
+    }
+    let posts = select.order_by_asc(PostColumn::Id).all(&state.conn).await;
+
+    match posts {
+        Ok(posts) => (StatusCode::OK, Json(posts)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每個測試使用獨立的 in-memory 資料庫，連線數設為 1 才會共用同一個資料庫
+    async fn test_db() -> DatabaseConnection {
+        let mut options = ConnectOptions::new("sqlite::memory:");
+        options.max_connections(1).sqlx_logging(false);
+        let conn = Database::connect(options).await.unwrap();
+        Migrator::up(&conn, None).await.unwrap();
+        conn
+    }
+
+    #[tokio::test]
+    async fn search_highlights_are_escaped_html() {
+        let conn = test_db().await;
+        PostActiveModel {
+            title: ActiveValue::Set("<script>alert(1)</script> SeaORM tips".to_string()),
+            text: ActiveValue::Set("Use SeaORM & <b>SQLite</b>".to_string()),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+
+        let fts = fts_query("seaorm").unwrap();
+        let hits = find_search_hits(&conn, fts, &Viewer::Service, 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].title_highlight,
+            "&lt;script&gt;alert(1)&lt;/script&gt; <mark>SeaORM</mark> tips"
+        );
+        assert!(hits[0].snippet.starts_with("Use <mark>SeaORM</mark> &amp; &lt;b&gt;"));
+        assert!(!hits[0].snippet.contains("<b>"));
+        // title 是純文字，不是 HTML
+        assert_eq!(hits[0].title, "<script>alert(1)</script> SeaORM tips");
+    }
+
+    #[tokio::test]
+    async fn duplicate_slug_is_a_conflict() {
+        let conn = test_db().await;
+        let post = |title: &str| PostActiveModel {
+            title: ActiveValue::Set(title.to_string()),
+            text: ActiveValue::Set(String::new()),
+            slug: ActiveValue::Set("same-slug".to_string()),
+            ..Default::default()
+        };
+        post("First").insert(&conn).await.unwrap();
+        let err = post("Second").insert(&conn).await.unwrap_err();
+        assert!(is_slug_conflict(&err));
+
+        let response = db_error_response(err);
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "slug already exists");
+    }
+
+    #[test]
+    fn marked_html_only_emits_mark_tags() {
+        let text = format!("a{}<img src=x onerror=\"alert('x')\">{}b", MARK_START, MARK_END);
+        assert_eq!(
+            marked_html(&text),
+            "a<mark>&lt;img src=x onerror=&quot;alert(&#39;x&#39;)&quot;&gt;</mark>b"
+        );
+    }
+}
@@ -7,9 +7,22 @@ struct Post {
     text: String,
 }
 
+/// GET /posts 回傳的分頁外層
+#[derive(Debug, Deserialize)]
+struct PostPage {
+    items: Vec<Post>,
+    total: u64,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
+    // 建立、修改與刪除文章都需要登入，腳本以 API key 代表使用者呼叫
+    // 先登入後以 POST /api-keys {"name": "ex08", "scopes": ["posts:write"]} 建立金鑰
+    let api_key = std::env::var("POSTS_API_KEY")
+        .map_err(|_| "請將具有 posts:write 權限的 API key 設定在 POSTS_API_KEY 環境變數")?;
+    let mut auth_headers = reqwest::header::HeaderMap::new();
+    auth_headers.insert("x-api-key", api_key.parse()?);
+    let client = reqwest::Client::builder().default_headers(auth_headers).build()?;
     let base_url = "http://localhost:3000/posts";
 
     println!("=== 開始 API 整合測試 ===");
@@ -23,39 +36,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         text: "使用 Reqwest 撰寫測試腳本".to_string(),
     };
 
-    let created_post: Post = client
+    let response = client
         .post(base_url)
         .json(&new_post)
         .send()
         .await?
-        .error_for_status()? // 檢查是否 2xx 成功
-        .json()
-        .await?;
+        .error_for_status()?; // 檢查是否 2xx 成功
+    let draft_etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .expect("Server should return ETag")
+        .clone();
+    let created_post: Post = response.json().await?;
 
     println!("   成功! 已建立文章: {:?}", created_post);
     let post_id = created_post.id.expect("Server should return ID");
 
+    // 新文章是草稿，發佈後匿名讀者才看得到
+    client
+        .post(format!("{}/{}/publish", base_url, post_id))
+        .header(reqwest::header::IF_MATCH, draft_etag)
+        .send()
+        .await?
+        .error_for_status()?;
+    println!("   成功! 文章已發佈");
+
     // 2. 查詢所有文章 (List)
     println!("\n2. 測試查詢列表 (GET)...");
-    let posts: Vec<Post> = client
+    let page: PostPage = client
         .get(base_url)
         .send()
         .await?
         .json()
         .await?;
-    println!("   目前共有 {} 篇文章", posts.len());
+    println!("   目前共有 {} 篇文章 (本頁 {} 篇)", page.total, page.items.len());
 
     // 3. 查詢單一文章 (Get)
     println!("\n3. 測試查詢單一文章 (GET ID)...");
     let single_url = format!("{}/{}", base_url, post_id);
-    let fetched_post: Post = client
+    let response = client.get(&single_url).send().await?;
+    // 更新與刪除都必須帶 If-Match，因此先記下 ETag
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .expect("Server should return ETag")
+        .clone();
+    let fetched_post: Post = response.json().await?;
+    assert_eq!(fetched_post.title, new_post.title);
+    println!("   成功! 資料正確 (ETag: {:?})", etag);
+
+    // 帶上相同 ETag 的 If-None-Match 應回傳 304
+    let status = client
         .get(&single_url)
+        .header(reqwest::header::IF_NONE_MATCH, etag.clone())
         .send()
         .await?
-        .json()
-        .await?;
-    assert_eq!(fetched_post.title, new_post.title);
-    println!("   成功! 資料正確");
+        .status();
+    assert_eq!(status, reqwest::StatusCode::NOT_MODIFIED);
+    println!("   成功! If-None-Match 回傳 304");
 
     // 4. 更新文章 (Update)
     println!("\n4. 測試更新文章 (PUT)...");
@@ -65,20 +103,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         text: "內容也更新了".to_string(),
     };
     
-    let updated_post: Post = client
+    let response = client
         .put(&single_url)
+        .header(reqwest::header::IF_MATCH, etag.clone())
         .json(&update_data)
         .send()
         .await?
-        .json()
-        .await?;
+        .error_for_status()?;
+    let new_etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .expect("Server should return ETag")
+        .clone();
+    let updated_post: Post = response.json().await?;
     
     assert_eq!(updated_post.title, "Rust 測試導論 (已更新)");
     println!("   成功! 更新後標題: {}", updated_post.title);
 
+    // 使用過期的 ETag 再更新一次，應回傳 412
+    let status = client
+        .put(&single_url)
+        .header(reqwest::header::IF_MATCH, etag)
+        .json(&update_data)
+        .send()
+        .await?
+        .status();
+    assert_eq!(status, reqwest::StatusCode::PRECONDITION_FAILED);
+    println!("   成功! 過期的 ETag 回傳 412");
+
     // 5. 刪除文章 (Delete)
     println!("\n5. 測試刪除文章 (DELETE)...");
-    let status = client.delete(&single_url).send().await?.status();
+    let status = client
+        .delete(&single_url)
+        .header(reqwest::header::IF_MATCH, new_etag)
+        .send()
+        .await?
+        .status();
     
     if status.is_success() {
         println!("   成功! 文章已刪除");
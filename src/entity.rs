@@ -1,16 +1,190 @@
 use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveValue, QuerySelect};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::HashSet;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+use super::slug::{candidate, has_base, slugify};
+use super::slug_redirect_entity as slug_redirect;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[schema(as = Post)]
 #[sea_orm(table_name = "posts")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub title: String,
     pub text: String,
+    /// 樂觀鎖版本號，每次更新 + 1，並作為 ETag 使用
+    #[sea_orm(default_value = 1)]
+    pub version: i32,
+    /// 作者 (users.id)，導入作者前建立的文章沒有作者
+    pub author_id: Option<i32>,
+    /// 建立與最後修改時間，由 `before_save` 設定 (透過 SQL 寫入時由 trigger 補上)
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub created_at: Option<DateTimeUtc>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub updated_at: Option<DateTimeUtc>,
+    /// 軟刪除時間，不為 NULL 代表已刪除
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub deleted_at: Option<DateTimeUtc>,
+    /// 發佈狀態，只能依 draft → published → archived 的順序轉換
+    pub status: PostStatus,
+    /// 發佈時間，晚於現在代表排程發佈，時間到之前仍不公開
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub published_at: Option<DateTimeUtc>,
+    /// 網址用的 slug，由 `before_save` 依標題產生，改標題後舊 slug 保留為轉址
+    #[sea_orm(unique)]
+    pub slug: String,
+}
+
+/// 文章的發佈狀態，在資料庫中以字串儲存
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "published")]
+    Published,
+    #[sea_orm(string_value = "archived")]
+    Archived,
 }
 
+impl PostStatus {
+    /// 允許的狀態轉換：draft → published → archived，不能倒退或跳過
+    pub fn can_transition_to(self, next: PostStatus) -> bool {
+        matches!(
+            (self, next),
+            (PostStatus::Draft, PostStatus::Published) | (PostStatus::Published, PostStatus::Archived)
+        )
+    }
+}
+
+/// 每篇文章屬於一位作者，作者被刪除時 author_id 設為 NULL
+/// 一篇文章可以有多則留言
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user_entity::Entity",
+        from = "Column::AuthorId",
+        to = "super::user_entity::Column::Id",
+        on_delete = "SetNull"
+    )]
+    Author,
+    #[sea_orm(has_many = "super::comment_entity::Entity")]
+    Comments,
+    #[sea_orm(has_many = "super::slug_redirect_entity::Entity")]
+    SlugRedirects,
+    #[sea_orm(has_many = "super::revision_entity::Entity")]
+    Revisions,
+}
+
+impl Related<super::user_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Author.def()
+    }
+}
+
+impl Related<super::comment_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Comments.def()
+    }
+}
+
+impl Related<slug_redirect::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SlugRedirects.def()
+    }
+}
+
+impl Related<super::revision_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Revisions.def()
+    }
+}
+
+/// 文章與標籤為多對多，經由 post_tags 關聯表
+impl Related<super::tag_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::post_tag_entity::Relation::Tag.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::post_tag_entity::Relation::Post.def().rev())
+    }
+}
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// 新增時設定 created_at，每次儲存都更新 updated_at
+    /// 新增時依標題產生 slug；標題改變且不再對應原本的 slug 時產生新的 slug，並將舊 slug 留作轉址
+    async fn before_save<C>(mut self, db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now();
+        if insert {
+            self.created_at = ActiveValue::Set(Some(now));
+        }
+        self.updated_at = ActiveValue::Set(Some(now));
+
+        let ActiveValue::Set(title) = &self.title else {
+            return Ok(self);
+        };
+        let base = slugify(title);
+        match (&self.slug, &self.id) {
+            (ActiveValue::NotSet, _) if insert => {
+                self.slug = ActiveValue::Set(unique_slug(db, &base, None).await?);
+            }
+            (ActiveValue::Unchanged(current), ActiveValue::Unchanged(id)) if !has_base(current, &base) => {
+                let (current, id) = (current.clone(), *id);
+                let slug = unique_slug(db, &base, Some(id)).await?;
+                // 改回以前用過的標題時，該舊 slug 重新成為目前的 slug，不再是轉址
+                slug_redirect::Entity::delete_by_id(slug.clone()).exec(db).await?;
+                let redirect = slug_redirect::ActiveModel {
+                    slug: ActiveValue::Set(current),
+                    post_id: ActiveValue::Set(id),
+                };
+                slug_redirect::Entity::insert(redirect)
+                    .on_conflict(OnConflict::column(slug_redirect::Column::Slug).do_nothing().to_owned())
+                    .exec_without_returning(db)
+                    .await?;
+                self.slug = ActiveValue::Set(slug);
+            }
+            _ => {}
+        }
+        Ok(self)
+    }
+}
+
+/// 找出第一個沒被使用的候選 slug (`base`、`base-2`、`base-3` …)
+/// 其他文章目前的 slug 與舊 slug 都視為已使用；`post_id` 自己的舊 slug 可以重新使用
+async fn unique_slug<C: ConnectionTrait>(db: &C, base: &str, post_id: Option<i32>) -> Result<String, DbErr> {
+    let pattern = format!("{}%", base);
+    let mut taken: HashSet<String> = Entity::find()
+        .select_only()
+        .column(Column::Slug)
+        .filter(Column::Slug.like(&pattern))
+        .into_tuple::<String>()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+    let redirects = slug_redirect::Entity::find()
+        .filter(slug_redirect::Column::Slug.like(&pattern))
+        .all(db)
+        .await?;
+    taken.extend(
+        redirects
+            .into_iter()
+            .filter(|r| Some(r.post_id) != post_id)
+            .map(|r| r.slug),
+    );
+
+    Ok((1..)
+        .map(|n| candidate(base, n))
+        .find(|slug| !taken.contains(slug))
+        .expect("an unbounded candidate sequence always has a free slug"))
+}
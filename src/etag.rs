@@ -0,0 +1,118 @@
+//! ETag 與條件式請求 (If-Match / If-None-Match) 的共用工具
+//! ETag 直接取自資料列的 version 欄位，每次更新 version + 1
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+
+/// 以資料列的 version 產生強 ETag，例如 `"3"`
+pub fn etag(version: i64) -> HeaderValue {
+    header_value(&quoted(version, None))
+}
+
+/// 同一版本的其他表示法 (例如 `?render=html`) 以後綴區分，例如 `"3-html"`
+/// 內容不同的回應不能共用強 ETag，否則 If-None-Match 可能讓客戶端沿用另一種表示法的快取
+#[allow(dead_code)] // 只有 ex07 的文章有多種表示法
+pub fn variant_etag(version: i64, variant: &str) -> HeaderValue {
+    header_value(&quoted(version, Some(variant)))
+}
+
+fn quoted(version: i64, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) => format!("\"{}-{}\"", version, variant),
+        None => format!("\"{}\"", version),
+    }
+}
+
+fn header_value(tag: &str) -> HeaderValue {
+    HeaderValue::from_str(tag).expect("quoted version is a valid header value")
+}
+
+// 比對 header 中以逗號分隔的 ETag 列表是否包含目前的 ETag
+// 弱比較時 W/"3" 與 "3" 視為相同
+fn list_matches(value: &str, current: &str, weak: bool) -> bool {
+    value.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag == current || (weak && tag.strip_prefix("W/") == Some(current))
+    })
+}
+
+/// GET 時檢查 If-None-Match，客戶端快取仍是最新版本時回傳 true (應回 304)
+/// 依 RFC 9110 使用弱比較
+pub fn not_modified(headers: &HeaderMap, version: i64) -> bool {
+    if_none_match(headers, &quoted(version, None))
+}
+
+/// 與 `not_modified` 相同，比對 `variant_etag` 產生的 ETag
+#[allow(dead_code)] // 只有 ex07 的文章有多種表示法
+pub fn variant_not_modified(headers: &HeaderMap, version: i64, variant: &str) -> bool {
+    if_none_match(headers, &quoted(version, Some(variant)))
+}
+
+fn if_none_match(headers: &HeaderMap, current: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| list_matches(v, current, true))
+}
+
+/// PUT / PATCH / DELETE 前檢查 If-Match，依 RFC 9110 使用強比較
+/// - 沒有 If-Match: 428 Precondition Required，強制客戶端先 GET 取得 ETag
+/// - 版本不符: 412 Precondition Failed
+pub fn check_if_match(headers: &HeaderMap, version: i64) -> Result<(), (StatusCode, &'static str)> {
+    match headers.get(header::IF_MATCH).map(|v| v.to_str()) {
+        None => Err((StatusCode::PRECONDITION_REQUIRED, "If-Match header is required")),
+        Some(Ok(v)) if list_matches(v, &quoted(version, None), false) => Ok(()),
+        Some(_) => Err(precondition_failed()),
+    }
+}
+
+/// 檢查通過後，實際寫入時仍以 `WHERE version = ?` 保護
+/// 若此時影響 0 筆，代表期間被其他請求修改，同樣回傳 412
+pub fn precondition_failed() -> (StatusCode, &'static str) {
+    (StatusCode::PRECONDITION_FAILED, "Resource was modified by another request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn etag_is_quoted_version() {
+        assert_eq!(etag(3), "\"3\"");
+        assert_eq!(variant_etag(3, "html"), "\"3-html\"");
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison_over_list() {
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "\"3\""), 3));
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "\"1\", W/\"3\""), 3));
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "*"), 3));
+        assert!(!not_modified(&headers(header::IF_NONE_MATCH, "\"2\", \"4\""), 3));
+        assert!(!not_modified(&HeaderMap::new(), 3));
+    }
+
+    #[test]
+    fn variants_do_not_match_each_other() {
+        let raw = headers(header::IF_NONE_MATCH, "\"3\"");
+        let html = headers(header::IF_NONE_MATCH, "\"3-html\"");
+        assert!(!variant_not_modified(&raw, 3, "html"));
+        assert!(variant_not_modified(&html, 3, "html"));
+        assert!(!not_modified(&html, 3));
+    }
+
+    #[test]
+    fn if_match_requires_header_and_strong_match() {
+        assert_eq!(check_if_match(&HeaderMap::new(), 3).unwrap_err().0, StatusCode::PRECONDITION_REQUIRED);
+        assert!(check_if_match(&headers(header::IF_MATCH, "\"2\", \"3\""), 3).is_ok());
+        assert!(check_if_match(&headers(header::IF_MATCH, "*"), 3).is_ok());
+        let stale = check_if_match(&headers(header::IF_MATCH, "\"2\""), 3).unwrap_err();
+        assert_eq!(stale.0, StatusCode::PRECONDITION_FAILED);
+        // 強比較：弱 ETag 與其他表示法的 ETag 都不能用於寫入
+        assert!(check_if_match(&headers(header::IF_MATCH, "W/\"3\""), 3).is_err());
+        assert!(check_if_match(&headers(header::IF_MATCH, "\"3-html\""), 3).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! 健康檢查 (ex04 / ex06 / ex07 共用)
+//!
+//! - `GET /healthz`: process 還活著就回傳 200，不檢查任何依賴；失敗代表需要重新啟動
+//! - `GET /readyz`: 逐一檢查依賴 (資料庫連線、migration 是否為最新)，任一項失敗回傳 503，
+//!   代表暫時不要把流量導過來；回應中列出每一項的狀態、耗時與錯誤原因。
+//!   正在關閉 (見 src/shutdown.rs) 時也回傳 503，status 為 `draining`
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// 單項檢查的時間上限，超過視為失敗
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+/// 單項依賴的檢查結果
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    pub status: CheckStatus,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// GET /readyz 的回應
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    /// `ready`、`not_ready` 或 `draining`
+    #[schema(value_type = String)]
+    status: &'static str,
+    #[schema(value_type = BTreeMap<String, Check>)]
+    checks: BTreeMap<&'static str, Check>,
+}
+
+/// 執行一項檢查並計時，回傳 Err 或逾時都視為失敗
+#[allow(dead_code)] // ex04 沒有需要檢查的依賴
+pub async fn check<F>(probe: F) -> Check
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let start = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+    Check {
+        status: if result.is_ok() { CheckStatus::Up } else { CheckStatus::Down },
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        error: result.err(),
+    }
+}
+
+/// GET /healthz
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "ops",
+    summary = "process 是否存活",
+    responses((status = 200, description = "存活", body = Object, example = json!({ "status": "ok" }))),
+)]
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// 彙整所有檢查結果，任一項失敗或正在關閉時回傳 503
+pub fn readiness(draining: bool, checks: Vec<(&'static str, Check)>) -> Response {
+    let ready = !draining && checks.iter().all(|(_, check)| check.status == CheckStatus::Up);
+    let body = Readiness {
+        status: match (draining, ready) {
+            (true, _) => "draining",
+            (false, true) => "ready",
+            (false, false) => "not_ready",
+        },
+        checks: checks.into_iter().collect(),
+    };
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(body)).into_response()
+}
@@ -0,0 +1,259 @@
+//! 共用的 HTTP middleware (ex04 / ex06 / ex07 共用)：CORS、回應壓縮、請求大小限制、逾時與安全性 header
+//!
+//! 以環境變數設定：
+//! - `CORS_ALLOW_ORIGINS`: 允許跨來源呼叫的前端，以逗號分隔，例如 `http://localhost:5173,https://app.example.com`，
+//!   `*` 代表任何來源；未設定時不回傳 CORS header，瀏覽器只允許同源呼叫
+//! - `CORS_ALLOW_METHODS`: 允許的方法，預設 `GET,POST,PUT,PATCH,DELETE`
+//! - `COMPRESSION`: 啟用的壓縮方式，以逗號分隔的 `gzip`、`br`、`zstd`，預設三者都啟用；`off` 代表不壓縮。
+//!   依 `Accept-Encoding` 選擇，只壓縮 JSON 與文字且至少 1 KiB 的回應
+//! - `BODY_LIMIT`: 請求 body 的上限 (bytes)，預設 1 MiB，超過回傳 413
+//! - `REQUEST_TIMEOUT`: 單一請求的處理時間上限 (秒)，預設 30，逾時回傳 408；0 代表不限制
+//! - `HSTS_MAX_AGE`: `Strict-Transport-Security` 的 max-age (秒)，預設一年；0 代表不送
+//! - `CONTENT_SECURITY_POLICY`: 預設 `default-src 'none'; frame-ancestors 'none'`，API 只回傳資料，
+//!   不需要載入任何資源；/docs 會自行指定允許載入 Swagger UI 的 CSP
+//!
+//! `X-Content-Type-Options: nosniff` 一律加上。已由 handler 設定的 header 不會被覆蓋
+
+use axum::extract::DefaultBodyLimit;
+use axum::http::{header, Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Version};
+use axum::Router;
+use std::str::FromStr;
+use std::time::Duration;
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tower_http::timeout::TimeoutLayer;
+
+const CORS_ORIGINS_ENV: &str = "CORS_ALLOW_ORIGINS";
+const CORS_METHODS_ENV: &str = "CORS_ALLOW_METHODS";
+const COMPRESSION_ENV: &str = "COMPRESSION";
+const BODY_LIMIT_ENV: &str = "BODY_LIMIT";
+const TIMEOUT_ENV: &str = "REQUEST_TIMEOUT";
+const HSTS_ENV: &str = "HSTS_MAX_AGE";
+const CSP_ENV: &str = "CONTENT_SECURITY_POLICY";
+
+const DEFAULT_METHODS: [Method; 5] = [Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE];
+const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+const DEFAULT_TIMEOUT: u64 = 30;
+const DEFAULT_HSTS_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+const DEFAULT_CSP: &str = "default-src 'none'; frame-ancestors 'none'";
+/// 瀏覽器可以快取 preflight 結果的時間
+const CORS_MAX_AGE: Duration = Duration::from_secs(600);
+/// 小於此大小的回應不壓縮，省下的流量不值得多花的 CPU
+const MIN_COMPRESS_SIZE: u16 = 1024;
+
+/// 前端可以帶的 header：JSON、登入、條件式請求 (ETag)、API key 與 request id
+const ALLOW_HEADERS: [HeaderName; 6] = [
+    header::CONTENT_TYPE,
+    header::AUTHORIZATION,
+    header::IF_MATCH,
+    header::IF_NONE_MATCH,
+    HeaderName::from_static("x-api-key"),
+    HeaderName::from_static("x-request-id"),
+];
+/// 前端可以讀取的回應 header (預設只能讀 Content-Type 等少數幾個)
+const EXPOSE_HEADERS: [HeaderName; 7] = [
+    header::ETAG,
+    header::LOCATION,
+    header::RETRY_AFTER,
+    HeaderName::from_static("x-request-id"),
+    HeaderName::from_static("ratelimit-limit"),
+    HeaderName::from_static("ratelimit-remaining"),
+    HeaderName::from_static("ratelimit-reset"),
+];
+
+/// 依環境變數建立的 middleware 設定
+pub struct HttpLayers {
+    cors: Option<CorsLayer>,
+    compression: Option<Encodings>,
+    body_limit: usize,
+    timeout: Option<Duration>,
+    hsts: Option<HeaderValue>,
+    csp: HeaderValue,
+}
+
+impl HttpLayers {
+    pub fn from_env() -> Result<HttpLayers, String> {
+        let cors = match env(CORS_ORIGINS_ENV) {
+            Some(origins) => {
+                let methods = match env(CORS_METHODS_ENV) {
+                    Some(methods) => parse_methods(&methods)?,
+                    None => DEFAULT_METHODS.to_vec(),
+                };
+                Some(
+                    CorsLayer::new()
+                        .allow_origin(parse_origins(&origins)?)
+                        .allow_methods(methods)
+                        .allow_headers(ALLOW_HEADERS)
+                        .expose_headers(EXPOSE_HEADERS)
+                        .max_age(CORS_MAX_AGE),
+                )
+            }
+            None => None,
+        };
+        let compression = match env(COMPRESSION_ENV) {
+            Some(value) => parse_encodings(&value)?,
+            None => Some(Encodings { gzip: true, br: true, zstd: true }),
+        };
+        let body_limit = number_from_env(BODY_LIMIT_ENV, DEFAULT_BODY_LIMIT)?;
+        let timeout = number_from_env(TIMEOUT_ENV, DEFAULT_TIMEOUT)?;
+        let hsts = number_from_env(HSTS_ENV, DEFAULT_HSTS_MAX_AGE)?;
+        let csp = env(CSP_ENV).unwrap_or_else(|| DEFAULT_CSP.to_string());
+
+        Ok(HttpLayers {
+            cors,
+            compression,
+            body_limit,
+            timeout: (timeout > 0).then(|| Duration::from_secs(timeout)),
+            hsts: (hsts > 0).then(|| {
+                HeaderValue::try_from(format!("max-age={}; includeSubDomains", hsts)).expect("digits are a valid header")
+            }),
+            csp: HeaderValue::try_from(csp).map_err(|_| format!("Invalid {}", CSP_ENV))?,
+        })
+    }
+
+    /// 套用到已設定好 state 的 router 上
+    /// 由內到外：body 大小限制、逾時、壓縮、安全性 header、CORS (preflight 不會進到路由)
+    pub fn apply(self, router: Router) -> Router {
+        let mut router = router
+            // 改由 RequestBodyLimitLayer 限制，Json 等 extractor 不再套用 axum 預設的 2 MiB
+            .layer(DefaultBodyLimit::disable())
+            .layer(RequestBodyLimitLayer::new(self.body_limit));
+        if let Some(timeout) = self.timeout {
+            router = router.layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, timeout));
+        }
+        if let Some(encodings) = self.compression {
+            // 串流壓縮：邊讀取 body 邊輸出，不需要先把整個回應讀進記憶體
+            router = router.layer(
+                CompressionLayer::new()
+                    .gzip(encodings.gzip)
+                    .br(encodings.br)
+                    .zstd(encodings.zstd)
+                    .compress_when(SizeAbove::new(MIN_COMPRESS_SIZE).and(compressible)),
+            );
+        }
+        router = router
+            .layer(SetResponseHeaderLayer::if_not_present(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ))
+            .layer(SetResponseHeaderLayer::if_not_present(header::CONTENT_SECURITY_POLICY, self.csp));
+        if let Some(hsts) = self.hsts {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(header::STRICT_TRANSPORT_SECURITY, hsts));
+        }
+        if let Some(cors) = self.cors {
+            router = router.layer(cors);
+        }
+        router
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn number_from_env<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid {} `{}`, expected a non-negative number", name, value)),
+        None => Ok(default),
+    }
+}
+
+fn parse_origins(value: &str) -> Result<AllowOrigin, String> {
+    if value.trim() == "*" {
+        return Ok(AllowOrigin::any());
+    }
+    let origins = value
+        .split(',')
+        .map(|origin| {
+            let origin = origin.trim();
+            // 瀏覽器送出的 Origin 沒有路徑，結尾的 / 會讓比對永遠失敗
+            let valid = (origin.starts_with("http://") || origin.starts_with("https://")) && !origin.ends_with('/');
+            valid
+                .then(|| HeaderValue::from_str(origin).ok())
+                .flatten()
+                .ok_or_else(|| format!("Invalid CORS origin `{}`, expected e.g. https://app.example.com", origin))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(AllowOrigin::list(origins))
+}
+
+fn parse_methods(value: &str) -> Result<Vec<Method>, String> {
+    value
+        .split(',')
+        .map(|method| {
+            Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("Invalid HTTP method `{}` in {}", method.trim(), CORS_METHODS_ENV))
+        })
+        .collect()
+}
+
+/// 啟用的壓縮方式
+#[derive(Debug, PartialEq)]
+struct Encodings {
+    gzip: bool,
+    br: bool,
+    zstd: bool,
+}
+
+// 解析 `gzip,br,zstd` 或 `off`，`off` 時回傳 None
+fn parse_encodings(value: &str) -> Result<Option<Encodings>, String> {
+    if value.trim().eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    let mut encodings = Encodings { gzip: false, br: false, zstd: false };
+    for encoding in value.split(',') {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" => encodings.gzip = true,
+            "br" => encodings.br = true,
+            "zstd" => encodings.zstd = true,
+            other => {
+                return Err(format!("Invalid {} `{}`, expected gzip, br, zstd or off", COMPRESSION_ENV, other));
+            }
+        }
+    }
+    Ok(Some(encodings))
+}
+
+// 只壓縮 JSON 與文字的回應 (圖片等格式本身已經壓縮過)
+fn compressible(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    content_type.starts_with("text/")
+        || content_type.starts_with("application/json")
+        || content_type.starts_with("application/javascript")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_encodings_accepts_lists_and_off() {
+        assert_eq!(parse_encodings("off"), Ok(None));
+        assert_eq!(parse_encodings(" gzip , BR "), Ok(Some(Encodings { gzip: true, br: true, zstd: false })));
+        assert_eq!(parse_encodings("zstd"), Ok(Some(Encodings { gzip: false, br: false, zstd: true })));
+        assert!(parse_encodings("gzip,deflate").is_err());
+        assert!(parse_encodings("gzip,,br").is_err());
+    }
+
+    #[test]
+    fn only_text_and_json_are_compressible() {
+        let check = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+            compressible(StatusCode::OK, Version::HTTP_11, &headers, &Extensions::new())
+        };
+        assert!(check("application/json"));
+        assert!(check("text/plain; charset=utf-8"));
+        assert!(!check("image/png"));
+        assert!(!check("application/octet-stream"));
+    }
+}
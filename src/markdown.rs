@@ -0,0 +1,57 @@
+//! 文章內文的 Markdown 轉 HTML
+//!
+//! 內文由使用者輸入，Markdown 允許直接嵌入 HTML，
+//! 因此轉換後一律經過 ammonia 清理：移除 `<script>`、`<iframe>`、`on*` 事件屬性、
+//! `javascript:` 連結等，並替連結加上 `rel="noopener noreferrer"`。
+
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// 快取最多保留的文章數，超過時隨意淘汰一筆
+const CACHE_CAPACITY: usize = 1024;
+
+/// 將 Markdown 轉為清理過、可直接嵌入頁面的 HTML
+pub fn render_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    ammonia::clean(&unsafe_html)
+}
+
+/// 以 (文章 id, version) 為鍵的轉換結果快取
+/// 每篇文章只保留最新版本的結果，文章更新後 version 改變，舊的結果自然失效
+#[derive(Default)]
+pub struct RenderCache {
+    entries: Mutex<HashMap<i32, (i32, Arc<str>)>>,
+}
+
+impl RenderCache {
+    /// 取得快取中的 HTML，沒有或版本不符時重新轉換並寫入快取
+    pub fn get_or_render(&self, id: i32, version: i32, markdown: &str) -> Arc<str> {
+        if let Some((cached_version, html)) = self.entries.lock().unwrap().get(&id)
+            && *cached_version == version
+        {
+            return html.clone();
+        }
+
+        // 轉換時不持有鎖，避免大篇文章擋住其他請求
+        let html: Arc<str> = render_html(markdown).into();
+        let mut entries = self.entries.lock().unwrap();
+        // 轉換期間其他請求可能已寫入更新的版本，不要以舊版覆蓋
+        if entries.get(&id).is_some_and(|(cached_version, _)| *cached_version > version) {
+            return html;
+        }
+        if entries.len() >= CACHE_CAPACITY
+            && !entries.contains_key(&id)
+            && let Some(evicted) = entries.keys().next().copied()
+        {
+            entries.remove(&evicted);
+        }
+        entries.insert(id, (version, html.clone()));
+        html
+    }
+}
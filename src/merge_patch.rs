@@ -0,0 +1,98 @@
+//! JSON Merge Patch (RFC 7396) 的欄位型別
+//! ex06 與 ex07 的 PATCH handler 以 #[path] 引入此模組
+
+use serde::{Deserialize, Deserializer};
+
+/// PATCH 請求中單一欄位的三種狀態
+/// - `Missing`: JSON 沒有這個欄位，保留原值
+/// - `Null`: 明確給 `null`，代表清除 (只有可為空的欄位允許)
+/// - `Value`: 給了新值
+///
+/// 欄位需搭配 `#[serde(default)]`，沒出現時才會是 `Missing`
+#[derive(Debug, Default)]
+pub enum PatchField<T> {
+    #[default]
+    Missing,
+    Null,
+    Value(T),
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for PatchField<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // 欄位有出現才會呼叫到這裡，因此 None 一定是明確的 null
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => PatchField::Value(value),
+            None => PatchField::Null,
+        })
+    }
+}
+
+impl<T> PatchField<T> {
+    /// 用於 NOT NULL 欄位：`null` 視為錯誤，回傳 Some(新值) 或 None(不修改)
+    pub fn required(self, name: &str) -> Result<Option<T>, String> {
+        match self {
+            PatchField::Missing => Ok(None),
+            PatchField::Null => Err(format!("`{}` cannot be null", name)),
+            PatchField::Value(value) => Ok(Some(value)),
+        }
+    }
+
+    /// 用於可為空的欄位：None(不修改)、Some(None)(清除) 或 Some(Some(新值))
+    #[allow(dead_code)] // ex06 沒有可為空的欄位
+    pub fn nullable(self) -> Option<Option<T>> {
+        match self {
+            PatchField::Missing => None,
+            PatchField::Null => Some(None),
+            PatchField::Value(value) => Some(Some(value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Deserialize)]
+    #[serde(default)]
+    struct Patch {
+        title: PatchField<String>,
+        author_id: PatchField<i32>,
+    }
+
+    fn parse(json: &str) -> Patch {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn distinguishes_missing_null_and_value() {
+        let patch = parse(r#"{"author_id": null}"#);
+        assert!(matches!(patch.title, PatchField::Missing));
+        assert!(matches!(patch.author_id, PatchField::Null));
+
+        let patch = parse(r#"{"title": "Rust", "author_id": 7}"#);
+        assert!(matches!(patch.title, PatchField::Value(ref t) if t == "Rust"));
+        assert!(matches!(patch.author_id, PatchField::Value(7)));
+    }
+
+    #[test]
+    fn wrong_type_is_an_error() {
+        assert!(serde_json::from_str::<Patch>(r#"{"author_id": "7"}"#).is_err());
+    }
+
+    #[test]
+    fn required_rejects_null_only() {
+        assert_eq!(PatchField::<i32>::Missing.required("title"), Ok(None));
+        assert_eq!(PatchField::Value(1).required("title"), Ok(Some(1)));
+        assert_eq!(
+            PatchField::<i32>::Null.required("title"),
+            Err("`title` cannot be null".to_string())
+        );
+    }
+
+    #[test]
+    fn nullable_keeps_null_as_clear() {
+        assert_eq!(PatchField::<i32>::Missing.nullable(), None);
+        assert_eq!(PatchField::<i32>::Null.nullable(), Some(None));
+        assert_eq!(PatchField::Value(7).nullable(), Some(Some(7)));
+    }
+}
@@ -0,0 +1,253 @@
+//! Prometheus 指標 (ex04 / ex06 / ex07 共用)
+//!
+//! - `http_requests_total` / `http_request_duration_seconds`: 依 method、路由樣板與狀態碼分類
+//! - `http_requests_in_flight`: 處理中的請求數
+//! - `db_query_duration_seconds` / `db_query_errors_total`: 依資料庫後端與 SQL 種類 (SELECT、INSERT...) 分類
+//! - `db_pool_connections` / `db_pool_max_connections`: 連線池使用狀況，在讀取 `/metrics` 時取得
+//! - `app_build_info`: 版本與執行檔名稱
+//!
+//! 路由以樣板 (`/users/{id}`) 而非實際路徑分類，沒有對應路由的請求記為 `unmatched`，
+//! 避免任意路徑造成無限多組標籤
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Prometheus text format 的 Content-Type
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// 請求耗時的 histogram 區間 (秒)
+const HTTP_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// SQL 耗時的 histogram 區間 (秒)，大多數查詢在 1ms 以內
+const DB_BUCKETS: [f64; 11] = [0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default)]
+struct Metrics {
+    requests: Mutex<BTreeMap<RequestKey, Histogram>>,
+    in_flight: AtomicI64,
+    queries: Mutex<BTreeMap<QueryKey, Histogram>>,
+    query_errors: Mutex<BTreeMap<QueryKey, u64>>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestKey {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct QueryKey {
+    backend: &'static str,
+    operation: &'static str,
+}
+
+// 累積的 histogram：buckets[i] 為耗時 <= 第 i 個區間上限的次數
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, bounds: &[f64], seconds: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; bounds.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(bounds) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    // 輸出 _bucket / _sum / _count，labels 為已經格式化好的 `a="1",b="2"`
+    fn render(&self, out: &mut String, name: &str, labels: &str, bounds: &[f64]) {
+        for (count, bound) in self.buckets.iter().zip(bounds) {
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// 連線池在某個時間點的狀態
+#[allow(dead_code)] // ex04 沒有資料庫
+#[derive(Clone, Copy, Debug)]
+pub struct PoolStats {
+    /// `rusqlite` 或 `sea-orm`
+    pub backend: &'static str,
+    pub max: u32,
+    /// 目前開啟的連線數 (使用中 + 閒置)
+    pub open: u32,
+    pub idle: u32,
+}
+
+/// 記錄請求數量、耗時與處理中的請求數
+/// 以 `layer` 套用，限流擋下的請求與 404 也會被計入
+pub async fn track(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+
+    let start = Instant::now();
+    let response = {
+        // 請求被取消 (client 中斷連線) 時 future 會直接被 drop，以 guard 確保計數一定會減回來
+        let _in_flight = InFlight::start();
+        next.run(request).await
+    };
+    let key = RequestKey {
+        method,
+        route,
+        status: response.status().as_u16(),
+    };
+    lock(&METRICS.requests)
+        .entry(key)
+        .or_default()
+        .observe(&HTTP_BUCKETS, start.elapsed().as_secs_f64());
+    response
+}
+
+struct InFlight;
+
+impl InFlight {
+    fn start() -> InFlight {
+        METRICS.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        METRICS.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 記錄一條 SQL 的耗時，失敗的語句另外計入 `db_query_errors_total`
+#[allow(dead_code)] // ex04 沒有資料庫
+pub fn observe_query(backend: &'static str, statement: &str, elapsed: Duration, failed: bool) {
+    let key = QueryKey {
+        backend,
+        operation: operation(statement),
+    };
+    lock(&METRICS.queries)
+        .entry(key)
+        .or_default()
+        .observe(&DB_BUCKETS, elapsed.as_secs_f64());
+    if failed {
+        *lock(&METRICS.query_errors).entry(key).or_default() += 1;
+    }
+}
+
+// SQL 的第一個關鍵字，其餘 (PRAGMA、BEGIN、CREATE...) 一律歸為 OTHER
+fn operation(statement: &str) -> &'static str {
+    let keyword = statement.split_whitespace().next().unwrap_or_default();
+    ["SELECT", "INSERT", "UPDATE", "DELETE", "WITH"]
+        .into_iter()
+        .find(|op| keyword.eq_ignore_ascii_case(op))
+        .unwrap_or("OTHER")
+}
+
+/// 以 Prometheus text format 輸出所有指標
+pub fn render(pools: &[PoolStats]) -> Response {
+    let mut out = String::new();
+
+    out.push_str("# HELP app_build_info Build information.\n# TYPE app_build_info gauge\n");
+    let _ = writeln!(
+        out,
+        "app_build_info{{binary=\"{}\",version=\"{}\",profile=\"{}\"}} 1",
+        env!("CARGO_BIN_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        if cfg!(debug_assertions) { "debug" } else { "release" },
+    );
+
+    {
+        let requests = lock(&METRICS.requests);
+        out.push_str("# HELP http_requests_total Total number of HTTP requests.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (key, histogram) in requests.iter() {
+            let _ = writeln!(out, "http_requests_total{{{}}} {}", key.labels(), histogram.count);
+        }
+        out.push_str("# HELP http_request_duration_seconds HTTP request latency.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (key, histogram) in requests.iter() {
+            histogram.render(&mut out, "http_request_duration_seconds", &key.labels(), &HTTP_BUCKETS);
+        }
+    }
+
+    out.push_str("# HELP http_requests_in_flight Number of HTTP requests being processed.\n");
+    out.push_str("# TYPE http_requests_in_flight gauge\n");
+    let _ = writeln!(out, "http_requests_in_flight {}", METRICS.in_flight.load(Ordering::Relaxed));
+
+    {
+        let queries = lock(&METRICS.queries);
+        out.push_str("# HELP db_query_duration_seconds Database query latency.\n");
+        out.push_str("# TYPE db_query_duration_seconds histogram\n");
+        for (key, histogram) in queries.iter() {
+            histogram.render(&mut out, "db_query_duration_seconds", &key.labels(), &DB_BUCKETS);
+        }
+        out.push_str("# HELP db_query_errors_total Total number of failed database queries.\n");
+        out.push_str("# TYPE db_query_errors_total counter\n");
+        for (key, count) in lock(&METRICS.query_errors).iter() {
+            let _ = writeln!(out, "db_query_errors_total{{{}}} {}", key.labels(), count);
+        }
+    }
+
+    if !pools.is_empty() {
+        out.push_str("# HELP db_pool_connections Open database connections by state.\n");
+        out.push_str("# TYPE db_pool_connections gauge\n");
+        for pool in pools {
+            let in_use = pool.open.saturating_sub(pool.idle);
+            let _ = writeln!(out, "db_pool_connections{{backend=\"{}\",state=\"in_use\"}} {}", pool.backend, in_use);
+            let _ = writeln!(out, "db_pool_connections{{backend=\"{}\",state=\"idle\"}} {}", pool.backend, pool.idle);
+        }
+        out.push_str("# HELP db_pool_max_connections Maximum size of the connection pool.\n");
+        out.push_str("# TYPE db_pool_max_connections gauge\n");
+        for pool in pools {
+            let _ = writeln!(out, "db_pool_max_connections{{backend=\"{}\"}} {}", pool.backend, pool.max);
+        }
+    }
+
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], out).into_response()
+}
+
+impl RequestKey {
+    fn labels(&self) -> String {
+        format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            escape(&self.method),
+            escape(&self.route),
+            self.status
+        )
+    }
+}
+
+impl QueryKey {
+    fn labels(&self) -> String {
+        format!("backend=\"{}\",operation=\"{}\"", self.backend, self.operation)
+    }
+}
+
+// 標籤值中的 \、" 與換行需要跳脫
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// 指標更新不會在持有鎖時 panic，遇到 poisoned 也直接沿用
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
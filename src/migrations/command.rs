@@ -0,0 +1,37 @@
+//! `migrate` 子命令的參數解析，ex06 與 ex07 共用
+//!
+//! 用法:
+//! - `cargo run --bin ex06_api_crud -- migrate up`       套用所有未執行的 migration
+//! - `cargo run --bin ex06_api_crud -- migrate down [n]` 回滾最後 n 個 (預設 1)
+//! - `cargo run --bin ex06_api_crud -- migrate status`   列出每個 migration 是否已套用
+//!
+//! 不帶子命令啟動時，伺服器會在啟動前自動套用未執行的 migration。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateCommand {
+    Up,
+    Down(u32),
+    Status,
+}
+
+impl MigrateCommand {
+    /// 解析命令列參數 (不含程式名稱)
+    /// 第一個參數不是 `migrate` 時回傳 Ok(None)，代表正常啟動伺服器
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut args = args.iter().map(String::as_str);
+        if args.next() != Some("migrate") {
+            return Ok(None);
+        }
+        let command = match (args.next(), args.next()) {
+            (Some("up"), None) => MigrateCommand::Up,
+            (Some("down"), None) => MigrateCommand::Down(1),
+            (Some("down"), Some(n)) => match n.parse::<u32>() {
+                Ok(n) if n > 0 => MigrateCommand::Down(n),
+                _ => return Err(format!("Invalid step count: {}", n)),
+            },
+            (Some("status"), None) => MigrateCommand::Status,
+            _ => return Err("Usage: migrate <up | down [steps] | status>".to_string()),
+        };
+        Ok(Some(command))
+    }
+}
@@ -0,0 +1,915 @@
+//! posts.db (ex07, SeaORM) 的版本化 schema migration
+//!
+//! 使用 `sea-orm-migration`，已套用的 migration 記錄在 `seaql_migrations` 表。
+//! 每個 migration 是一個以編號開頭的子模組，並以模組名稱作為 migration 名稱，例如 `m0002_add_posts_version`。
+//! (所有 migration 放在同一個檔案，`DeriveMigrationName` 取的是檔名，因此手動實作 `MigrationName`)
+//! 新增欄位時在 `Migrator::migrations()` 尾端加上新的模組即可，不需刪除 posts.db。
+//! 已發佈的 migration 不要再修改內容。
+
+use sea_orm_migration::prelude::*;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m0001_create_posts::Migration),
+            Box::new(m0002_add_posts_version::Migration),
+            Box::new(m0003_posts_fts::Migration),
+            Box::new(m0004_users_and_post_author::Migration),
+            Box::new(m0005_comments_and_tags::Migration),
+            Box::new(m0006_posts_timestamps::Migration),
+            Box::new(m0007_posts_status::Migration),
+            Box::new(m0008_posts_slug::Migration),
+            Box::new(m0009_post_revisions::Migration),
+            Box::new(m0010_users_roles::Migration),
+            Box::new(m0011_api_keys::Migration),
+            Box::new(m0012_users_version::Migration),
+        ]
+    }
+}
+
+#[derive(DeriveIden)]
+enum Posts {
+    Table,
+    Id,
+    Title,
+    Text,
+    Version,
+    AuthorId,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+    Status,
+    PublishedAt,
+    Slug,
+}
+
+#[derive(DeriveIden)]
+enum PostRevisions {
+    Table,
+    Id,
+    PostId,
+    Version,
+    Title,
+    Text,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ApiKeys {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Prefix,
+    KeyHash,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum PostSlugRedirects {
+    Table,
+    Slug,
+    PostId,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    Username,
+    Email,
+    Role,
+    PasswordHash,
+    Version,
+}
+
+#[derive(DeriveIden)]
+enum Comments {
+    Table,
+    Id,
+    PostId,
+    ParentId,
+    Body,
+}
+
+#[derive(DeriveIden)]
+enum Tags {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum PostTags {
+    Table,
+    PostId,
+    TagId,
+}
+
+// posts 的 FTS 同步 trigger，重建 posts 表後需要重新建立
+const POSTS_FTS_TRIGGERS: [&str; 3] = [
+    "CREATE TRIGGER IF NOT EXISTS posts_fts_insert AFTER INSERT ON posts BEGIN
+        INSERT INTO posts_fts (rowid, title, text) VALUES (new.id, new.title, new.text);
+    END",
+    "CREATE TRIGGER IF NOT EXISTS posts_fts_delete AFTER DELETE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, text) VALUES ('delete', old.id, old.title, old.text);
+    END",
+    "CREATE TRIGGER IF NOT EXISTS posts_fts_update AFTER UPDATE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, text) VALUES ('delete', old.id, old.title, old.text);
+        INSERT INTO posts_fts (rowid, title, text) VALUES (new.id, new.title, new.text);
+    END",
+];
+
+mod m0001_create_posts {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0001_create_posts"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        // 使用 IF NOT EXISTS，讓導入 migration 前就已存在的 posts.db 也能套用
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Posts::Table)
+                        .if_not_exists()
+                        .col(
+                            ColumnDef::new(Posts::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(Posts::Title).string().not_null())
+                        .col(ColumnDef::new(Posts::Text).string().not_null())
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .drop_table(Table::drop().table(Posts::Table).to_owned())
+                .await
+        }
+    }
+}
+
+mod m0002_add_posts_version {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0002_add_posts_version"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        // 樂觀鎖版本號，既有資料皆為版本 1
+        // 導入 migration 前的程式版本可能已經自行加過欄位，因此先檢查
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            if manager.has_column("posts", "version").await? {
+                return Ok(());
+            }
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Posts::Table)
+                        .add_column(ColumnDef::new(Posts::Version).integer().not_null().default(1))
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .get_connection()
+                .execute_unprepared("ALTER TABLE posts DROP COLUMN version")
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+mod m0003_posts_fts {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0003_posts_fts"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 建立 posts 的 FTS5 全文檢索索引
+        /// 使用 external content table (content='posts')，索引本身不重複儲存內容，
+        /// 並以 trigger 在 posts 新增 / 更新 / 刪除時同步索引，任何寫入路徑都不會漏掉。
+        /// tokenizer 採用 trigram，中文標題沒有空白斷詞也能以子字串搜尋 (查詢字詞至少 3 個字元)。
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            conn.execute_unprepared(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS posts_fts USING fts5(
+                    title, text, content='posts', content_rowid='id', tokenize='trigram'
+                )",
+            )
+            .await?;
+            for sql in POSTS_FTS_TRIGGERS {
+                conn.execute_unprepared(sql).await?;
+            }
+            // 把既有的文章全部補進索引
+            conn.execute_unprepared("INSERT INTO posts_fts (posts_fts) VALUES ('rebuild')")
+                .await?;
+            Ok(())
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            let statements = [
+                "DROP TRIGGER IF EXISTS posts_fts_insert",
+                "DROP TRIGGER IF EXISTS posts_fts_delete",
+                "DROP TRIGGER IF EXISTS posts_fts_update",
+                "DROP TABLE IF EXISTS posts_fts",
+            ];
+            for sql in statements {
+                conn.execute_unprepared(sql).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+mod m0004_users_and_post_author {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0004_users_and_post_author"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 建立 users 表，並在 posts 加上可為空的 author_id 外鍵
+        /// 既有文章沒有作者，author_id 為 NULL；作者被刪除時也設回 NULL 而不是連帶刪除文章
+        /// SQLite 不支援 ALTER TABLE ADD CONSTRAINT，外鍵直接寫在 ADD COLUMN 的欄位定義中
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Users::Table)
+                        .if_not_exists()
+                        .col(
+                            ColumnDef::new(Users::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(Users::Username).string().not_null().unique_key())
+                        .col(ColumnDef::new(Users::Email).string().not_null().unique_key())
+                        .to_owned(),
+                )
+                .await?;
+
+            let conn = manager.get_connection();
+            conn.execute_unprepared(
+                "ALTER TABLE posts ADD COLUMN author_id integer NULL
+                 REFERENCES users (id) ON DELETE SET NULL",
+            )
+            .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_posts_author_id")
+                        .table(Posts::Table)
+                        .col(Posts::AuthorId)
+                        .to_owned(),
+                )
+                .await
+        }
+
+        /// 帶外鍵的欄位無法 DROP COLUMN，只能以 m0003 時的結構重建 posts 表
+        /// 重建會一併刪除 FTS trigger，因此最後重新建立並重建索引
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            let statements = [
+                "DROP INDEX IF EXISTS idx_posts_author_id",
+                "CREATE TABLE posts_rollback (
+                    id integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                    title varchar NOT NULL,
+                    text varchar NOT NULL,
+                    version integer NOT NULL DEFAULT 1
+                )",
+                "INSERT INTO posts_rollback (id, title, text, version)
+                 SELECT id, title, text, version FROM posts",
+                "DROP TABLE posts",
+                "ALTER TABLE posts_rollback RENAME TO posts",
+            ];
+            for sql in statements.into_iter().chain(POSTS_FTS_TRIGGERS) {
+                conn.execute_unprepared(sql).await?;
+            }
+            conn.execute_unprepared("INSERT INTO posts_fts (posts_fts) VALUES ('rebuild')")
+                .await?;
+            manager
+                .drop_table(Table::drop().table(Users::Table).to_owned())
+                .await
+        }
+    }
+}
+
+mod m0005_comments_and_tags {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0005_comments_and_tags"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 建立 comments (一篇文章多則留言，parent_id 形成討論串) 與 tags / post_tags (多對多)
+        /// 外鍵皆為 ON DELETE CASCADE，刪除文章時留言與標籤關聯一併刪除
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Comments::Table)
+                        .col(
+                            ColumnDef::new(Comments::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(Comments::PostId).integer().not_null())
+                        .col(ColumnDef::new(Comments::ParentId).integer().null())
+                        .col(ColumnDef::new(Comments::Body).string().not_null())
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(Comments::Table, Comments::PostId)
+                                .to(Posts::Table, Posts::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(Comments::Table, Comments::ParentId)
+                                .to(Comments::Table, Comments::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_comments_post_id")
+                        .table(Comments::Table)
+                        .col(Comments::PostId)
+                        .to_owned(),
+                )
+                .await?;
+
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Tags::Table)
+                        .col(
+                            ColumnDef::new(Tags::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(Tags::Name).string().not_null().unique_key())
+                        .to_owned(),
+                )
+                .await?;
+
+            manager
+                .create_table(
+                    Table::create()
+                        .table(PostTags::Table)
+                        .col(ColumnDef::new(PostTags::PostId).integer().not_null())
+                        .col(ColumnDef::new(PostTags::TagId).integer().not_null())
+                        .primary_key(Index::create().col(PostTags::PostId).col(PostTags::TagId))
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(PostTags::Table, PostTags::PostId)
+                                .to(Posts::Table, Posts::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(PostTags::Table, PostTags::TagId)
+                                .to(Tags::Table, Tags::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            // 主鍵已涵蓋以 post_id 查詢，另外為以標籤找文章建立索引
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_post_tags_tag_id")
+                        .table(PostTags::Table)
+                        .col(PostTags::TagId)
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            for table in [
+                PostTags::Table.into_iden(),
+                Tags::Table.into_iden(),
+                Comments::Table.into_iden(),
+            ] {
+                manager.drop_table(Table::drop().table(table).to_owned()).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+mod m0006_posts_timestamps {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0006_posts_timestamps"
+        }
+    }
+
+    // 與 chrono 的 RFC 3339 格式相容的目前 UTC 時間
+    const NOW_SQL: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 新增 created_at / updated_at / deleted_at (軟刪除)
+        /// ALTER TABLE ADD COLUMN 不允許 CURRENT_TIMESTAMP 之類的非常數預設值，
+        /// 因此欄位可為 NULL，既有文章以 migration 當下的時間補上。
+        /// 透過 SeaORM 寫入時由 `ActiveModelBehavior::before_save` 設定時間，
+        /// trigger 則負責其他寫入路徑 (直接執行 SQL、update_many 等)。
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            for column in [Posts::CreatedAt, Posts::UpdatedAt, Posts::DeletedAt] {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(Posts::Table)
+                            .add_column(ColumnDef::new(column).timestamp_with_time_zone().null())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+
+            let conn = manager.get_connection();
+            let statements = [
+                format!(
+                    "UPDATE posts SET created_at = {now}, updated_at = {now} WHERE created_at IS NULL",
+                    now = NOW_SQL
+                ),
+                format!(
+                    "CREATE TRIGGER IF NOT EXISTS posts_set_created_at AFTER INSERT ON posts
+                     WHEN new.created_at IS NULL BEGIN
+                         UPDATE posts SET created_at = {now}, updated_at = {now} WHERE id = new.id;
+                     END",
+                    now = NOW_SQL
+                ),
+                format!(
+                    "CREATE TRIGGER IF NOT EXISTS posts_touch_updated_at AFTER UPDATE ON posts
+                     WHEN new.updated_at IS old.updated_at BEGIN
+                         UPDATE posts SET updated_at = {now} WHERE id = new.id;
+                     END",
+                    now = NOW_SQL
+                ),
+            ];
+            for sql in statements {
+                conn.execute_unprepared(&sql).await?;
+            }
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_posts_deleted_at")
+                        .table(Posts::Table)
+                        .col(Posts::DeletedAt)
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            let statements = [
+                "DROP TRIGGER IF EXISTS posts_set_created_at",
+                "DROP TRIGGER IF EXISTS posts_touch_updated_at",
+                "DROP INDEX IF EXISTS idx_posts_deleted_at",
+                "ALTER TABLE posts DROP COLUMN created_at",
+                "ALTER TABLE posts DROP COLUMN updated_at",
+                "ALTER TABLE posts DROP COLUMN deleted_at",
+            ];
+            for sql in statements {
+                conn.execute_unprepared(sql).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+mod m0007_posts_status {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0007_posts_status"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 新增發佈狀態 (draft / published / archived) 與發佈時間
+        /// 新文章預設為 draft；導入前的文章原本就是公開的，因此設為 published，發佈時間取建立時間
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Posts::Table)
+                        .add_column(
+                            ColumnDef::new(Posts::Status)
+                                .string_len(16)
+                                .not_null()
+                                .default("draft"),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Posts::Table)
+                        .add_column(ColumnDef::new(Posts::PublishedAt).timestamp_with_time_zone().null())
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .get_connection()
+                .execute_unprepared("UPDATE posts SET status = 'published', published_at = created_at")
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_posts_status_published_at")
+                        .table(Posts::Table)
+                        .col(Posts::Status)
+                        .col(Posts::PublishedAt)
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            let statements = [
+                "DROP INDEX IF EXISTS idx_posts_status_published_at",
+                "ALTER TABLE posts DROP COLUMN status",
+                "ALTER TABLE posts DROP COLUMN published_at",
+            ];
+            for sql in statements {
+                conn.execute_unprepared(sql).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+mod m0008_posts_slug {
+    use super::*;
+    use crate::slug::{candidate, slugify};
+    use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+    use std::collections::HashSet;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0008_posts_slug"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 新增 posts.slug (唯一) 與保存舊 slug 的 post_slug_redirects 表
+        /// ADD COLUMN NOT NULL 必須有常數預設值，先以空字串加入欄位，
+        /// 再依 id 順序為既有文章產生不重複的 slug，最後才建立唯一索引
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Posts::Table)
+                        .add_column(ColumnDef::new(Posts::Slug).string().not_null().default(""))
+                        .to_owned(),
+                )
+                .await?;
+
+            let conn = manager.get_connection();
+            let backend = conn.get_database_backend();
+            let rows = conn
+                .query_all(Statement::from_string(backend, "SELECT id, title FROM posts ORDER BY id"))
+                .await?;
+            let mut used = HashSet::new();
+            for row in rows {
+                let id: i32 = row.try_get("", "id")?;
+                let title: String = row.try_get("", "title")?;
+                let base = slugify(&title);
+                let slug = (1..)
+                    .map(|n| candidate(&base, n))
+                    .find(|slug| !used.contains(slug))
+                    .expect("an unbounded candidate sequence always has a free slug");
+                conn.execute(Statement::from_sql_and_values(
+                    backend,
+                    "UPDATE posts SET slug = ? WHERE id = ?",
+                    [slug.clone().into(), id.into()],
+                ))
+                .await?;
+                used.insert(slug);
+            }
+
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_posts_slug")
+                        .table(Posts::Table)
+                        .col(Posts::Slug)
+                        .unique()
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_table(
+                    Table::create()
+                        .table(PostSlugRedirects::Table)
+                        .col(ColumnDef::new(PostSlugRedirects::Slug).string().not_null().primary_key())
+                        .col(ColumnDef::new(PostSlugRedirects::PostId).integer().not_null())
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(PostSlugRedirects::Table, PostSlugRedirects::PostId)
+                                .to(Posts::Table, Posts::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .drop_table(Table::drop().table(PostSlugRedirects::Table).to_owned())
+                .await?;
+            let conn = manager.get_connection();
+            conn.execute_unprepared("DROP INDEX IF EXISTS idx_posts_slug").await?;
+            conn.execute_unprepared("ALTER TABLE posts DROP COLUMN slug").await?;
+            Ok(())
+        }
+    }
+}
+
+mod m0009_post_revisions {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0009_post_revisions"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 建立 post_revisions，保存每次修改前的 title / text
+        /// (post_id, version) 唯一，查詢單篇文章的修訂紀錄時也會用到這個索引
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(PostRevisions::Table)
+                        .col(
+                            ColumnDef::new(PostRevisions::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(PostRevisions::PostId).integer().not_null())
+                        .col(ColumnDef::new(PostRevisions::Version).integer().not_null())
+                        .col(ColumnDef::new(PostRevisions::Title).string().not_null())
+                        .col(ColumnDef::new(PostRevisions::Text).string().not_null())
+                        .col(ColumnDef::new(PostRevisions::CreatedAt).timestamp_with_time_zone().not_null())
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(PostRevisions::Table, PostRevisions::PostId)
+                                .to(Posts::Table, Posts::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_post_revisions_post_version")
+                        .table(PostRevisions::Table)
+                        .col(PostRevisions::PostId)
+                        .col(PostRevisions::Version)
+                        .unique()
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .drop_table(Table::drop().table(PostRevisions::Table).to_owned())
+                .await
+        }
+    }
+}
+
+mod m0010_users_roles {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0010_users_roles"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 新增使用者角色 (admin / editor / reader) 與登入用的密碼雜湊
+        /// 既有的使用者沒有密碼，無法登入，因此不升級任何人為 admin；
+        /// 之後第一位以密碼註冊的使用者成為 admin，再由其指派角色
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(
+                            ColumnDef::new(Users::Role)
+                                .string_len(16)
+                                .not_null()
+                                .default("reader"),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(ColumnDef::new(Users::PasswordHash).text().null())
+                        .to_owned(),
+                )
+                .await?;
+            Ok(())
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            let conn = manager.get_connection();
+            for sql in [
+                "ALTER TABLE users DROP COLUMN role",
+                "ALTER TABLE users DROP COLUMN password_hash",
+            ] {
+                conn.execute_unprepared(sql).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+mod m0011_api_keys {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0011_api_keys"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        /// 建立 api_keys，金鑰以 key_hash 查詢 (唯一索引)，列出使用者的金鑰時使用 user_id 索引
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(ApiKeys::Table)
+                        .col(
+                            ColumnDef::new(ApiKeys::Id)
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(ApiKeys::UserId).integer().not_null())
+                        .col(ColumnDef::new(ApiKeys::Name).string().not_null())
+                        .col(ColumnDef::new(ApiKeys::Prefix).string().not_null())
+                        .col(ColumnDef::new(ApiKeys::KeyHash).string().not_null().unique_key())
+                        .col(ColumnDef::new(ApiKeys::Scopes).string().not_null())
+                        .col(ColumnDef::new(ApiKeys::ExpiresAt).timestamp_with_time_zone().null())
+                        .col(ColumnDef::new(ApiKeys::LastUsedAt).timestamp_with_time_zone().null())
+                        .col(ColumnDef::new(ApiKeys::CreatedAt).timestamp_with_time_zone().not_null())
+                        .foreign_key(
+                            ForeignKey::create()
+                                .from(ApiKeys::Table, ApiKeys::UserId)
+                                .to(Users::Table, Users::Id)
+                                .on_delete(ForeignKeyAction::Cascade),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name("idx_api_keys_user_id")
+                        .table(ApiKeys::Table)
+                        .col(ApiKeys::UserId)
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .drop_table(Table::drop().table(ApiKeys::Table).to_owned())
+                .await
+        }
+    }
+}
+
+mod m0012_users_version {
+    use super::*;
+
+    pub struct Migration;
+
+    impl MigrationName for Migration {
+        fn name(&self) -> &str {
+            "m0012_users_version"
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MigrationTrait for Migration {
+        // 使用者的樂觀鎖版本號，指派角色時需要帶 If-Match (與 ex06 相同)，既有資料皆為版本 1
+        async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(ColumnDef::new(Users::Version).integer().not_null().default(1))
+                        .to_owned(),
+                )
+                .await
+        }
+
+        async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+            manager
+                .get_connection()
+                .execute_unprepared("ALTER TABLE users DROP COLUMN version")
+                .await?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! my_database.db (ex06, rusqlite) 的版本化 schema migration
+//!
+//! 每個 migration 有遞增的版本號與 up / down 兩個方向，
+//! 已套用的版本記錄在 `schema_migrations` 表。
+//! 新增欄位或索引時，在 `MIGRATIONS` 尾端加上新的版本即可，不需刪除資料庫檔案。
+//! 已發佈的 migration 不要再修改內容，否則已套用過的資料庫不會重新執行。
+
+use rusqlite::{params, Connection, Transaction};
+use std::collections::HashSet;
+
+/// 單一 migration，up / down 都在同一個交易中與版本記錄一起執行
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+    down: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// 依版本號排序的 migration 清單
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        up: create_users_up,
+        down: create_users_down,
+    },
+    Migration {
+        version: 2,
+        name: "unique_username_email",
+        up: unique_username_email_up,
+        down: unique_username_email_down,
+    },
+    Migration {
+        version: 3,
+        name: "add_users_version",
+        up: add_users_version_up,
+        down: add_users_version_down,
+    },
+    Migration {
+        version: 4,
+        name: "add_users_timestamps",
+        up: add_users_timestamps_up,
+        down: add_users_timestamps_down,
+    },
+    Migration {
+        version: 5,
+        name: "add_auth",
+        up: add_auth_up,
+        down: add_auth_down,
+    },
+    Migration {
+        version: 6,
+        name: "add_user_roles",
+        up: add_user_roles_up,
+        down: add_user_roles_down,
+    },
+];
+
+/// SQLite 產生目前 UTC 時間 (RFC 3339，毫秒精度) 的運算式
+pub const NOW_SQL: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+// 建立版本記錄表
+fn ensure_version_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version    INTEGER PRIMARY KEY,
+            name       TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// 取得已套用的版本號 (由小到大)
+pub fn applied_versions(conn: &Connection) -> rusqlite::Result<Vec<i64>> {
+    ensure_version_table(conn)?;
+    let mut stmt = conn.prepare("SELECT version FROM schema_migrations ORDER BY version")?;
+    let versions = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    Ok(versions)
+}
+
+/// 依序套用所有尚未執行的 migration
+pub fn migrate_up(conn: &mut Connection) -> anyhow::Result<()> {
+    let applied = applied_versions(conn)?;
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        tracing::info!("Applied migration {:04}_{}", migration.version, migration.name);
+    }
+    Ok(())
+}
+
+/// 由新到舊回滾最後 `steps` 個已套用的 migration
+pub fn migrate_down(conn: &mut Connection, steps: u32) -> anyhow::Result<()> {
+    let applied = applied_versions(conn)?;
+    for version in applied.iter().rev().take(steps as usize) {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.version == *version) else {
+            // 資料庫版本比程式新 (例如用舊版程式開啟)，無法得知如何回滾
+            anyhow::bail!("Unknown migration version {} in schema_migrations", version);
+        };
+        let tx = conn.transaction()?;
+        (migration.down)(&tx)?;
+        tx.execute(
+            "DELETE FROM schema_migrations WHERE version = ?1",
+            params![migration.version],
+        )?;
+        tx.commit()?;
+        tracing::info!("Rolled back migration {:04}_{}", migration.version, migration.name);
+    }
+    Ok(())
+}
+
+/// 尚未套用的 migration (由舊到新)，readiness 檢查用來確認 schema 是最新的
+pub fn pending_migrations(conn: &Connection) -> rusqlite::Result<Vec<&'static Migration>> {
+    let applied = applied_versions(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)).collect())
+}
+
+/// 列出每個 migration 的套用狀態
+pub fn print_status(conn: &Connection) -> rusqlite::Result<()> {
+    let applied = applied_versions(conn)?;
+    for migration in MIGRATIONS {
+        let status = if applied.contains(&migration.version) { "applied" } else { "pending" };
+        println!("{:04}_{:<28} {}", migration.version, migration.name, status);
+    }
+    Ok(())
+}
+
+// --- 0001: users 表 ---
+
+// 使用 IF NOT EXISTS，讓導入 migration 前就已存在的資料庫也能套用
+fn create_users_up(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS users (
+            id       INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT NOT NULL,
+            email    TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+fn create_users_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("DROP TABLE users", ())?;
+    Ok(())
+}
+
+// --- 0002: username / email 唯一索引 ---
+
+/// 為 username 與 email 建立唯一索引 (email 不分大小寫)
+/// 舊版的 my_database.db 沒有唯一限制，可能已存在重複資料，直接建索引會失敗。
+/// 因此先依 id 由小到大掃描，保留最早建立的那一筆，
+/// 其餘重複列搬到 users_conflicts 表保存，讓管理者事後人工處理，不會遺失資料。
+fn unique_username_email_up(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS users_conflicts (
+            id       INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            email    TEXT NOT NULL,
+            moved_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+
+    let duplicate_ids: Vec<i64> = {
+        let mut stmt = tx.prepare("SELECT id, username, email FROM users ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+
+        let mut seen_usernames = HashSet::new();
+        let mut seen_emails = HashSet::new();
+        let mut duplicates = Vec::new();
+        for row in rows {
+            let (id, username, email) = row?;
+            // 與 COLLATE NOCASE 一致：只折疊 ASCII 大小寫
+            let email = email.to_ascii_lowercase();
+            if seen_usernames.contains(&username) || seen_emails.contains(&email) {
+                duplicates.push(id);
+            } else {
+                seen_usernames.insert(username);
+                seen_emails.insert(email);
+            }
+        }
+        duplicates
+    };
+
+    for id in &duplicate_ids {
+        tx.execute(
+            "INSERT INTO users_conflicts (id, username, email)
+             SELECT id, username, email FROM users WHERE id = ?1",
+            params![id],
+        )?;
+        tx.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+    }
+    if !duplicate_ids.is_empty() {
+        tracing::warn!(
+            "發現 {} 筆重複的使用者資料，已移至 users_conflicts 表: {:?}",
+            duplicate_ids.len(),
+            duplicate_ids
+        );
+    }
+
+    tx.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_username ON users (username)",
+        (),
+    )?;
+    tx.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email ON users (email COLLATE NOCASE)",
+        (),
+    )?;
+    Ok(())
+}
+
+// users_conflicts 中可能有待處理的資料，回滾時保留不刪除
+fn unique_username_email_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP INDEX IF EXISTS idx_users_username;
+         DROP INDEX IF EXISTS idx_users_email;",
+    )
+}
+
+// --- 0003: 樂觀鎖版本號 ---
+
+// 既有資料皆為版本 1
+fn add_users_version_up(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "users", "version", "INTEGER NOT NULL DEFAULT 1")
+}
+
+fn add_users_version_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("ALTER TABLE users DROP COLUMN version", ())?;
+    Ok(())
+}
+
+// --- 0004: created_at / updated_at / deleted_at ---
+
+/// 新增建立時間、修改時間與軟刪除時間
+/// ALTER TABLE ADD COLUMN 不允許 CURRENT_TIMESTAMP 之類的非常數預設值，
+/// 因此欄位可為 NULL，既有資料以 migration 當下的時間補上，
+/// 之後由 trigger 維護：INSERT 時補上 created_at / updated_at，
+/// UPDATE 若沒有明確修改 updated_at 就自動更新為目前時間。
+fn add_users_timestamps_up(tx: &Transaction) -> rusqlite::Result<()> {
+    for column in ["created_at", "updated_at", "deleted_at"] {
+        add_column_if_missing(tx, "users", column, "TEXT")?;
+    }
+    tx.execute_batch(&format!(
+        "UPDATE users SET created_at = {now}, updated_at = {now} WHERE created_at IS NULL;
+         CREATE TRIGGER IF NOT EXISTS users_set_created_at AFTER INSERT ON users
+         WHEN new.created_at IS NULL BEGIN
+             UPDATE users SET created_at = {now}, updated_at = {now} WHERE id = new.id;
+         END;
+         CREATE TRIGGER IF NOT EXISTS users_touch_updated_at AFTER UPDATE ON users
+         WHEN new.updated_at IS old.updated_at BEGIN
+             UPDATE users SET updated_at = {now} WHERE id = new.id;
+         END;",
+        now = NOW_SQL
+    ))
+}
+
+fn add_users_timestamps_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER IF EXISTS users_set_created_at;
+         DROP TRIGGER IF EXISTS users_touch_updated_at;
+         ALTER TABLE users DROP COLUMN created_at;
+         ALTER TABLE users DROP COLUMN updated_at;
+         ALTER TABLE users DROP COLUMN deleted_at;",
+    )
+}
+
+// --- 0005: 密碼與 token 撤銷 ---
+
+/// users.password_hash 存放 argon2 雜湊，導入前建立的使用者沒有密碼 (NULL)，無法登入
+/// refresh_tokens 記錄每個簽發的 refresh token，換發或登出時標記 revoked_at
+/// revoked_access_tokens 記錄登出時仍未過期的 access token，過期後即可刪除
+/// expires_at 以 Unix 秒儲存，與 JWT 的 exp 相同
+fn add_auth_up(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "users", "password_hash", "TEXT")?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS refresh_tokens (
+            jti        TEXT PRIMARY KEY,
+            user_id    INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+            expires_at INTEGER NOT NULL,
+            revoked_at TEXT
+         );
+         CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user_id ON refresh_tokens (user_id);
+         CREATE TABLE IF NOT EXISTS revoked_access_tokens (
+            jti        TEXT PRIMARY KEY,
+            expires_at INTEGER NOT NULL
+         );",
+    )
+}
+
+fn add_auth_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TABLE IF EXISTS revoked_access_tokens;
+         DROP TABLE IF EXISTS refresh_tokens;
+         ALTER TABLE users DROP COLUMN password_hash;",
+    )
+}
+
+// --- 0006: 角色 ---
+
+/// users.role 為 admin / editor / reader，新使用者預設為 reader
+/// 既有資料中 id 最小、未刪除且設有密碼的使用者升為 admin，避免導入後沒有人能管理使用者
+/// 沒有密碼的使用者無法登入，不會被升級；沒有符合的使用者時由第一位註冊的使用者成為 admin
+fn add_user_roles_up(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(
+        tx,
+        "users",
+        "role",
+        "TEXT NOT NULL DEFAULT 'reader' CHECK (role IN ('admin', 'editor', 'reader'))",
+    )?;
+    tx.execute(
+        "UPDATE users SET role = 'admin'
+         WHERE id = (SELECT MIN(id) FROM users WHERE deleted_at IS NULL AND password_hash IS NOT NULL)
+           AND NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin')",
+        (),
+    )?;
+    Ok(())
+}
+
+fn add_user_roles_down(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE users DROP COLUMN role;")
+}
+
+// 透過 PRAGMA table_info 檢查欄位是否存在，不存在才 ALTER TABLE 新增
+// 導入 migration 前的程式版本可能已經自行加過欄位
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(Result::ok)
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), ())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! OpenAPI 3 文件與 Swagger UI (ex04 / ex06 / ex07 共用)
+//!
+//! - handler 以 `#[utoipa::path]` 說明，請求、回應與查詢參數的型別 derive `ToSchema` / `IntoParams`，
+//!   serde 的 `rename`、`skip`、`default` 等屬性都會反映在文件中
+//! - 路由以 utoipa-axum 的 `OpenApiRouter` 與 `routes!` 註冊，文件上的路徑與 method 一定與實際路由一致
+//! - Swagger UI 的靜態檔案在編譯時打包進執行檔 (utoipa-swagger-ui 的 `vendored`)，不從 CDN 載入
+//!
+//! `GET /openapi.json` 回傳文件，`GET /docs` 為互動式的 Swagger UI
+
+use axum::http::{header, HeaderValue};
+use axum::Router;
+use tower_http::set_header::SetResponseHeaderLayer;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::OpenApi;
+use utoipa::Modify;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_swagger_ui::SwaggerUi;
+
+/// [`BearerAuth`] 的 security scheme 名稱，`#[utoipa::path(security(...))]` 以相同的字串引用
+#[allow(dead_code)] // ex04 不需要登入
+pub const BEARER_AUTH: &str = "bearerAuth";
+/// [`ApiKeyAuth`] 的 security scheme 名稱
+#[allow(dead_code)] // 只有 ex07 支援 API key
+pub const API_KEY_AUTH: &str = "apiKeyAuth";
+
+/// /docs 的 CSP：Swagger UI 的檔案都來自同源，並以 fetch 讀取 /openapi.json
+/// Swagger UI 會動態插入 style，因此 style-src 需要 'unsafe-inline'
+const DOCS_CSP: &str = "default-src 'none'; script-src 'self'; style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data:; connect-src 'self'; frame-ancestors 'none'";
+
+/// 以 `Authorization: Bearer` 傳送的 token，參數為文件上的說明
+/// 例: `#[openapi(modifiers(&BearerAuth("POST /auth/login 取得的 access token")))]`
+#[allow(dead_code)] // ex04 不需要登入
+pub struct BearerAuth(pub &'static str);
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut OpenApi) {
+        let scheme = HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .bearer_format("JWT")
+            .description(Some(self.0))
+            .build();
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(BEARER_AUTH, SecurityScheme::Http(scheme));
+    }
+}
+
+/// 以 header 傳送的 API key
+#[allow(dead_code)] // 只有 ex07 支援 API key
+pub struct ApiKeyAuth {
+    pub header: &'static str,
+    pub description: &'static str,
+}
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut OpenApi) {
+        let scheme = ApiKey::Header(ApiKeyValue::with_description(self.header, self.description));
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(API_KEY_AUTH, SecurityScheme::ApiKey(scheme));
+    }
+}
+
+/// 完成註冊，取出文件並加上 `/openapi.json` 與 `/docs` (不列在文件中)
+pub fn finish<S>(router: OpenApiRouter<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let (router, mut document) = router.split_for_parts();
+    // Cargo.toml 沒有設定 license，utoipa 預設仍會產生名稱為空的 license
+    if document.info.license.as_ref().is_some_and(|license| license.name.is_empty()) {
+        document.info.license = None;
+    }
+    let docs = Router::from(SwaggerUi::new("/docs").url("/openapi.json", document)).layer(
+        SetResponseHeaderLayer::overriding(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static(DOCS_CSP)),
+    );
+    router.merge(docs)
+}
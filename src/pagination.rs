@@ -0,0 +1,131 @@
+//! 分頁、排序的共用工具
+//! ex06 (rusqlite) 與 ex07 (SeaORM) 都以 #[path] 引入此模組
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 未指定 limit 時的預設筆數
+pub const DEFAULT_LIMIT: u64 = 20;
+/// 單頁最多回傳筆數，避免一次撈出整張表
+pub const MAX_LIMIT: u64 = 100;
+
+/// 列表回應的外層包裝
+/// - `total`: 套用篩選條件後的總筆數 (不受分頁影響)
+/// - `next_cursor`: 下一頁可帶入 `?after=` 的值，沒有下一頁時為 null
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub limit: u64,
+    pub offset: u64,
+    pub next_cursor: Option<i64>,
+}
+
+/// 單一排序欄位，`-title` 代表依 title 遞減排序
+#[derive(Debug, Clone, Copy)]
+pub struct SortKey {
+    pub field: &'static str,
+    pub descending: bool,
+}
+
+/// 取得實際使用的 limit：未指定用預設值，超過上限則截斷
+pub fn effective_limit(limit: Option<u64>) -> u64 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// 解析 `?sort=field,-field`，只接受 `allowed` 中的欄位名稱
+/// 欄位名稱會直接組進 SQL，所以必須以白名單檢查，不能信任使用者輸入
+pub fn parse_sort(raw: Option<&str>, allowed: &[&'static str]) -> Result<Vec<SortKey>, String> {
+    let Some(raw) = raw else {
+        return Ok(Vec::new());
+    };
+
+    let mut keys = Vec::new();
+    for part in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, descending) = match part.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (part, false),
+        };
+        let field = allowed
+            .iter()
+            .copied()
+            .find(|f| *f == name)
+            .ok_or_else(|| format!("Unknown sort field: {} (allowed: {})", name, allowed.join(", ")))?;
+        keys.push(SortKey { field, descending });
+    }
+    Ok(keys)
+}
+
+/// 游標分頁以 id 為鍵，因此只能搭配預設排序或只依 id 排序
+/// 回傳 Some(true) 代表 id 遞減 (游標條件為 id < after)，None 代表排序不支援游標
+pub fn cursor_direction(sort: &[SortKey]) -> Option<bool> {
+    match sort {
+        [] => Some(false),
+        [key] if key.field == "id" => Some(key.descending),
+        _ => None,
+    }
+}
+
+/// 將 `?xxx_contains=` 的輸入轉為 LIKE 樣式，並跳脫 `%`、`_` 等萬用字元
+/// 搭配 SQL 的 `ESCAPE '\'` 使用
+pub fn contains_pattern(needle: &str) -> String {
+    let mut pattern = String::with_capacity(needle.len() + 2);
+    pattern.push('%');
+    for c in needle.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["id", "title", "created_at"];
+
+    #[test]
+    fn limit_defaults_and_clamps() {
+        assert_eq!(effective_limit(None), DEFAULT_LIMIT);
+        assert_eq!(effective_limit(Some(0)), 1);
+        assert_eq!(effective_limit(Some(50)), 50);
+        assert_eq!(effective_limit(Some(10_000)), MAX_LIMIT);
+    }
+
+    #[test]
+    fn sort_accepts_whitelisted_fields_in_order() {
+        let keys = parse_sort(Some("-created_at, title,"), FIELDS).unwrap();
+        let keys: Vec<_> = keys.iter().map(|k| (k.field, k.descending)).collect();
+        assert_eq!(keys, [("created_at", true), ("title", false)]);
+        assert!(parse_sort(None, FIELDS).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sort_rejects_unknown_fields_and_sql() {
+        let err = parse_sort(Some("password_hash"), FIELDS).unwrap_err();
+        assert!(err.contains("Unknown sort field: password_hash"));
+        assert!(parse_sort(Some("id; DROP TABLE users"), FIELDS).is_err());
+        assert!(parse_sort(Some("--id"), FIELDS).is_err());
+    }
+
+    #[test]
+    fn cursor_only_for_id_order() {
+        let sort = |raw| parse_sort(Some(raw), FIELDS).unwrap();
+        assert_eq!(cursor_direction(&[]), Some(false));
+        assert_eq!(cursor_direction(&sort("id")), Some(false));
+        assert_eq!(cursor_direction(&sort("-id")), Some(true));
+        assert_eq!(cursor_direction(&sort("title")), None);
+        assert_eq!(cursor_direction(&sort("id,title")), None);
+    }
+
+    #[test]
+    fn contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("rust"), "%rust%");
+        assert_eq!(contains_pattern("100%_off"), r"%100\%\_off%");
+        assert_eq!(contains_pattern(r"a\b"), r"%a\\b%");
+        assert_eq!(contains_pattern(""), "%%");
+    }
+}
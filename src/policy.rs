@@ -0,0 +1,109 @@
+//! 角色與權限檢查 (ex06 / ex07 共用)
+//!
+//! - admin: 管理使用者 (刪除、還原、指派角色)，並擁有 editor 的所有權限
+//! - editor: 可以修改、刪除任何人的文章，也看得到草稿
+//! - reader: 一般帳號，只能修改自己的資料與自己的文章
+//!
+//! 「本人」的判斷 (使用者本人、文章作者) 由 handler 傳入，權限只決定能否操作別人的資料
+
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 使用者角色，在資料庫中以字串儲存
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    #[sea_orm(string_value = "editor")]
+    Editor,
+    #[sea_orm(string_value = "reader")]
+    Reader,
+}
+
+/// 需要特定角色才能執行的操作
+/// ex06 只管理使用者、ex07 只管理文章，各自只會用到其中一部分
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// 修改其他使用者的資料
+    UpdateAnyUser,
+    DeleteUser,
+    RestoreUser,
+    AssignRole,
+    /// 查看未公開的文章 (草稿、排程中、已封存)
+    ReadDrafts,
+    /// 修改其他人的文章 (含發佈、封存、標籤、還原修訂紀錄)
+    UpdateAnyPost,
+    /// 刪除或還原其他人的文章
+    DeleteAnyPost,
+}
+
+impl Permission {
+    /// 403 回應中顯示的權限名稱
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::UpdateAnyUser => "users:update",
+            Permission::DeleteUser => "users:delete",
+            Permission::RestoreUser => "users:restore",
+            Permission::AssignRole => "users:assign-role",
+            Permission::ReadDrafts => "posts:read-drafts",
+            Permission::UpdateAnyPost => "posts:update",
+            Permission::DeleteAnyPost => "posts:delete",
+        }
+    }
+}
+
+impl Role {
+    /// 角色是否擁有此權限
+    pub fn grants(self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Editor => matches!(
+                permission,
+                Permission::ReadDrafts | Permission::UpdateAnyPost | Permission::DeleteAnyPost
+            ),
+            Role::Reader => false,
+        }
+    }
+
+    /// 本人可以直接操作；不是本人時需要角色擁有此權限
+    pub fn authorize(self, is_owner: bool, permission: Permission) -> Result<(), Denied> {
+        if is_owner || self.grants(permission) {
+            Ok(())
+        } else {
+            Err(Denied::Forbidden(permission))
+        }
+    }
+}
+
+/// 權限檢查失敗的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denied {
+    /// 沒有登入: 401
+    Unauthenticated,
+    /// 已登入但缺少權限: 403，回應中指出缺少的權限
+    Forbidden(Permission),
+}
+
+impl IntoResponse for Denied {
+    fn into_response(self) -> Response {
+        match self {
+            Denied::Unauthenticated => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Authentication required",
+            )
+                .into_response(),
+            Denied::Forbidden(permission) => (
+                StatusCode::FORBIDDEN,
+                format!("Missing permission: {}", permission.name()),
+            )
+                .into_response(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// posts 與 tags 的多對多關聯表，以 (post_id, tag_id) 為複合主鍵
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "post_tags")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub post_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub tag_id: i32,
+}
+
+/// 文章或標籤被刪除時，關聯一併刪除
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::entity::Entity",
+        from = "Column::PostId",
+        to = "super::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Post,
+    #[sea_orm(
+        belongs_to = "super::tag_entity::Entity",
+        from = "Column::TagId",
+        to = "super::tag_entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Tag,
+}
+
+impl Related<super::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Post.def()
+    }
+}
+
+impl Related<super::tag_entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tag.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,494 @@
+//! Token bucket 限流 middleware (ex04 / ex06 / ex07 共用)
+//!
+//! - 以 client 區分：已驗證身分的請求 (外層 middleware 在 extension 放入 [`Client`]) 以該身分計算，否則以來源 IP；
+//!   不以請求自帶的金鑰區分，換一把無效的金鑰不會得到新的額度
+//! - 以路由群組區分額度：GET / HEAD / OPTIONS 為 read，其餘方法為 write (預設較嚴格)，
+//!   個別路由 (例如登入) 可再指定更嚴格的額度
+//! - 每個回應都帶 `RateLimit-Limit` / `RateLimit-Remaining` / `RateLimit-Reset`，
+//!   超過額度回傳 429 並附上 `Retry-After`
+//! - 狀態預設存在記憶體；設定 `RATE_LIMIT_DB` 時改存在 SQLite 檔案，讓多個 process 共用同一份額度
+//!
+//! 額度以環境變數設定，格式為 `次數/單位`，單位可為 s、min、h，例如 `RATE_LIMIT_WRITE=20/min`
+
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{header, HeaderName, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const READ_ENV: &str = "RATE_LIMIT_READ";
+const WRITE_ENV: &str = "RATE_LIMIT_WRITE";
+const DB_ENV: &str = "RATE_LIMIT_DB";
+/// 記憶體中 bucket 數量的上限，達到上限時先清掉已經補滿的 bucket (與新建的沒有差別)，
+/// 仍然沒有空間時移除最久沒使用的 [`EVICT_BATCH`] 個
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+const EVICT_BATCH: usize = MAX_MEMORY_BUCKETS / 10;
+/// 兩次清理之間至少間隔的秒數，避免 bucket 數量一直在上限附近時每個請求都要掃過整個 HashMap
+const SWEEP_INTERVAL_SECS: f64 = 60.0;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// 額度：每 `period` 最多 `burst` 個請求，token 以固定速率持續補充
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    pub const fn per_minute(burst: u32) -> Self {
+        Quota {
+            burst,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    /// 解析 `次數/單位` 格式，例如 `120/min`
+    pub fn parse(value: &str) -> Result<Quota, String> {
+        let invalid = || format!("Invalid rate limit `{}`, expected e.g. 60/min", value);
+        let (burst, unit) = value.trim().split_once('/').ok_or_else(invalid)?;
+        let burst: u32 = burst.trim().parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?;
+        let secs = match unit.trim() {
+            "s" | "sec" => 1,
+            "m" | "min" => 60,
+            "h" | "hour" => 3600,
+            _ => return Err(invalid()),
+        };
+        Ok(Quota {
+            burst,
+            period: Duration::from_secs(secs),
+        })
+    }
+
+    // 每秒補充的 token 數
+    fn refill_rate(self) -> f64 {
+        f64::from(self.burst) / self.period.as_secs_f64()
+    }
+}
+
+/// 單一 bucket 的狀態，`updated_at` 為 Unix 秒
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: f64,
+}
+
+/// 一次檢查的結果
+#[derive(Clone, Copy, Debug)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// 補滿所需秒數
+    reset: u64,
+    /// 被拒絕時，下一個 token 可用前的秒數
+    retry_after: u64,
+}
+
+// 依經過的時間補充 token 後嘗試取用一個
+fn take(bucket: Option<Bucket>, quota: Quota, now: f64) -> (Bucket, Decision) {
+    let capacity = f64::from(quota.burst);
+    let rate = quota.refill_rate();
+    let mut tokens = match bucket {
+        Some(b) => (b.tokens + (now - b.updated_at).max(0.0) * rate).min(capacity),
+        None => capacity,
+    };
+    let allowed = tokens >= 1.0;
+    if allowed {
+        tokens -= 1.0;
+    }
+    let decision = Decision {
+        allowed,
+        limit: quota.burst,
+        remaining: tokens.floor() as u32,
+        reset: ((capacity - tokens) / rate).ceil() as u64,
+        retry_after: if allowed { 0 } else { ((1.0 - tokens) / rate).ceil().max(1.0) as u64 },
+    };
+    (Bucket { tokens, updated_at: now }, decision)
+}
+
+/// 已驗證的 client 身分，由限流之前的驗證 middleware 放入 request extension，
+/// 例如 `Client(format!("api-key:{}", id))`；沒有時以來源 IP 區分
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)] // ex04 沒有登入，不會放入
+pub struct Client(pub String);
+
+#[derive(Default)]
+struct MemoryBuckets {
+    buckets: HashMap<String, Bucket>,
+    /// 上次清理的時間 (Unix 秒)
+    last_sweep: f64,
+}
+
+impl MemoryBuckets {
+    // 新增 bucket 前確保數量低於上限，`idle` 為閒置多久後 bucket 一定已經補滿
+    fn make_room(&mut self, now: f64, idle: f64) {
+        if self.buckets.len() < MAX_MEMORY_BUCKETS {
+            return;
+        }
+        // 掃描整個 HashMap 的清理每 SWEEP_INTERVAL_SECS 最多一次
+        if now - self.last_sweep >= SWEEP_INTERVAL_SECS {
+            self.buckets.retain(|_, b| b.updated_at + idle > now);
+            self.last_sweep = now;
+        }
+        // 所有 bucket 都還在使用中 (例如大量 IP 輪流請求) 時，移除最久沒使用的一批，
+        // 數量不會超過上限；被移除的 client 下次從滿的 bucket 開始
+        if self.buckets.len() >= MAX_MEMORY_BUCKETS {
+            let mut by_age: Vec<(f64, String)> =
+                self.buckets.iter().map(|(key, b)| (b.updated_at, key.clone())).collect();
+            by_age.select_nth_unstable_by(EVICT_BATCH - 1, |a, b| a.0.total_cmp(&b.0));
+            for (_, key) in &by_age[..EVICT_BATCH] {
+                self.buckets.remove(key);
+            }
+        }
+    }
+}
+
+enum Store {
+    Memory(Mutex<MemoryBuckets>),
+    /// 多個 process 共用的 SQLite 檔案，以 BEGIN IMMEDIATE 確保讀取與寫入之間不會被其他 process 插隊
+    Sqlite(Arc<Mutex<Connection>>),
+}
+
+/// 限流器，以 `middleware::from_fn_with_state` 搭配 [`limit`] 使用
+pub struct RateLimiter {
+    read: Quota,
+    write: Quota,
+    /// 指定路由 (路由樣板，例如 `/auth/login`) 的額度，優先於 read / write
+    routes: Vec<(&'static str, Quota)>,
+    store: Store,
+}
+
+impl RateLimiter {
+    /// 以環境變數建立，未設定時 read 每分鐘 120 次、write 每分鐘 30 次，狀態存在記憶體
+    pub fn from_env() -> anyhow::Result<Self> {
+        let quota = |name: &str, default: Quota| match std::env::var(name) {
+            Ok(value) => Quota::parse(&value).map_err(anyhow::Error::msg),
+            Err(_) => Ok(default),
+        };
+        let store = match std::env::var(DB_ENV) {
+            Ok(path) if !path.is_empty() => Store::Sqlite(Arc::new(Mutex::new(open_store(&path)?))),
+            _ => Store::Memory(Mutex::default()),
+        };
+        Ok(RateLimiter {
+            read: quota(READ_ENV, Quota::per_minute(120))?,
+            write: quota(WRITE_ENV, Quota::per_minute(30))?,
+            routes: Vec::new(),
+            store,
+        })
+    }
+
+    /// 為特定路由設定獨立的額度 (例如登入，避免暴力猜測密碼)
+    #[allow(dead_code)] // ex04 沒有需要額外限制的路由
+    pub fn with_route(mut self, path: &'static str, quota: Quota) -> Self {
+        self.routes.push((path, quota));
+        self
+    }
+
+    // 決定請求所屬的路由群組與額度
+    fn group(&self, method: &Method, path: &str) -> (&'static str, Quota) {
+        if let Some((route, quota)) = self.routes.iter().find(|(route, _)| *route == path) {
+            return (route, *quota);
+        }
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            ("read", self.read)
+        } else {
+            ("write", self.write)
+        }
+    }
+
+    // 所有群組中最長的補滿時間，閒置超過此時間的 bucket 一定已經補滿
+    fn longest_period(&self) -> Duration {
+        self.routes
+            .iter()
+            .map(|(_, q)| q.period)
+            .chain([self.read.period, self.write.period])
+            .max()
+            .unwrap_or_default()
+    }
+
+    async fn check(&self, key: String, quota: Quota) -> Decision {
+        let now = unix_now();
+        match &self.store {
+            Store::Memory(buckets) => {
+                let mut memory = buckets.lock().unwrap_or_else(|e| e.into_inner());
+                if !memory.buckets.contains_key(&key) {
+                    memory.make_room(now, self.longest_period().as_secs_f64());
+                }
+                let (bucket, decision) = take(memory.buckets.get(&key).copied(), quota, now);
+                memory.buckets.insert(key, bucket);
+                decision
+            }
+            Store::Sqlite(conn) => {
+                let conn = conn.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+                    take_sqlite(&mut conn, &key, quota, now)
+                })
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string()));
+                match result {
+                    Ok(decision) => decision,
+                    // 限流狀態無法讀寫時放行請求，不因限流器故障而中斷服務
+                    Err(e) => {
+                        tracing::warn!(error = %e, "rate limit store error");
+                        take(None, quota, now).1
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn open_store(path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS rate_limit_buckets (
+            key        TEXT PRIMARY KEY,
+            tokens     REAL NOT NULL,
+            updated_at REAL NOT NULL
+         )",
+    )?;
+    Ok(conn)
+}
+
+fn take_sqlite(conn: &mut Connection, key: &str, quota: Quota, now: f64) -> rusqlite::Result<Decision> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let bucket = tx
+        .query_row(
+            "SELECT tokens, updated_at FROM rate_limit_buckets WHERE key = ?1",
+            params![key],
+            |row| {
+                Ok(Bucket {
+                    tokens: row.get(0)?,
+                    updated_at: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+    let (bucket, decision) = take(bucket, quota, now);
+    tx.execute(
+        "INSERT INTO rate_limit_buckets (key, tokens, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT (key) DO UPDATE SET tokens = excluded.tokens, updated_at = excluded.updated_at",
+        params![key, bucket.tokens, bucket.updated_at],
+    )?;
+    tx.commit()?;
+    Ok(decision)
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+// 識別 client：已驗證的身分優先，否則使用來源 IP
+fn client_key(request: &Request) -> String {
+    let extensions = request.extensions();
+    match (extensions.get::<Client>(), extensions.get::<ConnectInfo<SocketAddr>>()) {
+        (Some(Client(client)), _) => client.clone(),
+        (None, Some(ConnectInfo(addr))) => format!("ip:{}", addr.ip()),
+        (None, None) => "ip:unknown".to_string(),
+    }
+}
+
+/// 限流 middleware
+/// 以 `route_layer` 套用才取得到路由樣板；伺服器需以
+/// `into_make_service_with_connect_info::<SocketAddr>()` 啟動才能以 IP 區分 client。
+/// 要以身分區分時，驗證的 middleware 需加在限流外層並放入 [`Client`]
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(request.uri().path(), MatchedPath::as_str);
+    let (group, quota) = limiter.group(request.method(), path);
+    let key = format!("{}|{}", group, client_key(&request));
+
+    let decision = limiter.check(key, quota).await;
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(decision.retry_after));
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    fn limiter(read: Quota) -> Arc<RateLimiter> {
+        Arc::new(RateLimiter {
+            read,
+            write: read,
+            routes: Vec::new(),
+            store: Store::Memory(Mutex::default()),
+        })
+    }
+
+    fn app(limiter: Arc<RateLimiter>) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(limiter, limit))
+    }
+
+    fn request(api_key: &str, client: Option<&str>) -> Request {
+        let mut request = Request::builder().uri("/").header("x-api-key", api_key).body(Body::empty()).unwrap();
+        let addr: SocketAddr = "192.0.2.1:4000".parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(addr));
+        if let Some(client) = client {
+            request.extensions_mut().insert(Client(client.to_string()));
+        }
+        request
+    }
+
+    #[test]
+    fn parse_quota() {
+        assert_eq!(Quota::parse("60/min"), Ok(Quota::per_minute(60)));
+        assert_eq!(
+            Quota::parse(" 5 / s "),
+            Ok(Quota {
+                burst: 5,
+                period: Duration::from_secs(1)
+            })
+        );
+        assert_eq!(Quota::parse("100/h").unwrap().period, Duration::from_secs(3600));
+        for value in ["60", "0/min", "-1/min", "ten/min", "60/day", ""] {
+            assert!(Quota::parse(value).is_err(), "{} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn bucket_drains_and_refills_over_time() {
+        // 每秒補充一個 token
+        let quota = Quota::per_minute(60);
+        let (mut bucket, first) = take(None, quota, 0.0);
+        assert!(first.allowed);
+        assert_eq!((first.remaining, first.reset), (59, 1));
+        for _ in 1..60 {
+            bucket = take(Some(bucket), quota, 0.0).0;
+        }
+        let (bucket, denied) = take(Some(bucket), quota, 0.5);
+        assert!(!denied.allowed);
+        assert_eq!((denied.remaining, denied.retry_after), (0, 1));
+        let (bucket, refilled) = take(Some(bucket), quota, 1.5);
+        assert!(refilled.allowed);
+        assert_eq!(refilled.reset, 60);
+        assert!(!take(Some(bucket), quota, 1.5).1.allowed);
+    }
+
+    #[test]
+    fn idle_bucket_is_capped_at_burst() {
+        let quota = Quota::per_minute(2);
+        let drained = Bucket {
+            tokens: 0.0,
+            updated_at: 0.0,
+        };
+        let (_, decision) = take(Some(drained), quota, 3600.0);
+        assert_eq!(decision.remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn rotating_unverified_api_keys_shares_the_ip_bucket() {
+        let app = app(limiter(Quota::per_minute(2)));
+        let mut statuses = Vec::new();
+        for key in ["pk_a_1", "pk_b_2", "pk_c_3"] {
+            statuses.push(app.clone().oneshot(request(key, None)).await.unwrap().status());
+        }
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]);
+        let denied = app.oneshot(request("pk_d_4", None)).await.unwrap();
+        assert!(denied.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn verified_clients_have_their_own_bucket() {
+        let app = app(limiter(Quota::per_minute(1)));
+        let first = app.clone().oneshot(request("pk_a_1", None)).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let by_ip = app.clone().oneshot(request("pk_a_1", None)).await.unwrap();
+        assert_eq!(by_ip.status(), StatusCode::TOO_MANY_REQUESTS);
+        let by_key = app.oneshot(request("pk_a_1", Some("api-key:1"))).await.unwrap();
+        assert_eq!(by_key.status(), StatusCode::OK);
+        assert_eq!(by_key.headers()[RATELIMIT_LIMIT], "1");
+    }
+
+    #[tokio::test]
+    async fn full_store_is_swept_at_most_once_per_interval() {
+        let limiter = limiter(Quota::per_minute(60));
+        let Store::Memory(memory) = &limiter.store else {
+            unreachable!()
+        };
+        let fill = |count: usize, updated_at: f64| {
+            let mut memory = memory.lock().unwrap();
+            for i in 0..count {
+                memory.buckets.insert(
+                    format!("ip:{}", i),
+                    Bucket {
+                        tokens: 60.0,
+                        updated_at,
+                    },
+                );
+            }
+        };
+
+        // 閒置超過補滿時間的 bucket 被清掉，只剩這次請求的 bucket
+        fill(MAX_MEMORY_BUCKETS, 0.0);
+        limiter.check("read|new".to_string(), limiter.read).await;
+        assert_eq!(memory.lock().unwrap().buckets.len(), 1);
+
+        // 距離上次清理不到 SWEEP_INTERVAL_SECS 時不再掃描，只移除最久沒使用的一批
+        fill(MAX_MEMORY_BUCKETS - 1, 0.0);
+        limiter.check("read|other".to_string(), limiter.read).await;
+        let memory = memory.lock().unwrap();
+        assert_eq!(memory.buckets.len(), MAX_MEMORY_BUCKETS - EVICT_BATCH + 1);
+        assert!(memory.buckets.contains_key("read|new") && memory.buckets.contains_key("read|other"));
+    }
+
+    #[tokio::test]
+    async fn active_buckets_over_the_cap_evict_the_most_idle() {
+        let limiter = limiter(Quota::per_minute(60));
+        let Store::Memory(memory) = &limiter.store else {
+            unreachable!()
+        };
+        // 全部都在補滿時間內使用過，清理移除不了任何 bucket；ip:0 最久沒使用
+        let now = unix_now();
+        {
+            let mut memory = memory.lock().unwrap();
+            for i in 0..MAX_MEMORY_BUCKETS {
+                let bucket = Bucket {
+                    tokens: 0.0,
+                    updated_at: now - 30.0 + i as f64 * 0.001,
+                };
+                memory.buckets.insert(format!("ip:{}", i), bucket);
+            }
+        }
+        for i in 0..3 * EVICT_BATCH {
+            limiter.check(format!("read|rotating-{}", i), limiter.read).await;
+            assert!(memory.lock().unwrap().buckets.len() <= MAX_MEMORY_BUCKETS);
+        }
+        let memory = memory.lock().unwrap();
+        assert!(!memory.buckets.contains_key("ip:0"));
+        assert!(memory.buckets.contains_key(&format!("ip:{}", MAX_MEMORY_BUCKETS - 1)));
+    }
+}
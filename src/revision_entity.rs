@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 文章更新前的內容快照，每次修改 title / text 時保存一筆
+/// `version` 為快照當時文章的版本號，同一篇文章內不重複
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[schema(as = Revision)]
+#[sea_orm(table_name = "post_revisions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub post_id: i32,
+    pub version: i32,
+    pub title: String,
+    pub text: String,
+    /// 保存快照的時間，也就是這個版本被取代的時間
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTimeUtc,
+}
+
+/// 文章被刪除時，修訂紀錄一併刪除
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::entity::Entity",
+        from = "Column::PostId",
+        to = "super::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Post,
+}
+
+impl Related<super::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Post.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
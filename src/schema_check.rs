@@ -0,0 +1,122 @@
+//! 啟動時比對資料庫實際的資料表結構與 SeaORM Entity 定義
+//!
+//! Migration 與 Entity 是分開維護的，若有人改了 `entity::Model` 卻忘了補 migration
+//! (或反過來)，伺服器仍能啟動，但每個請求都會回 500。
+//! 這裡在啟動時先檢查一次，發現不一致就列出差異並拒絕啟動。
+
+use sea_orm::{ConnectionTrait, Database, DbErr, EntityTrait, Schema, Statement};
+
+/// PRAGMA table_info 回傳的欄位資訊
+struct ColumnInfo {
+    name: String,
+    declared_type: String,
+    not_null: bool,
+    primary_key: bool,
+    has_default: bool,
+}
+
+// 讀取資料表的欄位定義，資料表不存在時回傳空的 Vec
+async fn table_columns<C: ConnectionTrait>(conn: &C, table: &str) -> Result<Vec<ColumnInfo>, DbErr> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            conn.get_database_backend(),
+            format!("PRAGMA table_info(\"{}\")", table),
+        ))
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(ColumnInfo {
+                name: row.try_get("", "name")?,
+                declared_type: row.try_get("", "type")?,
+                not_null: row.try_get::<i32>("", "notnull")? != 0,
+                primary_key: row.try_get::<i32>("", "pk")? != 0,
+                has_default: row.try_get::<Option<String>>("", "dflt_value")?.is_some(),
+            })
+        })
+        .collect()
+}
+
+// SQLite 依宣告型別決定欄位的 type affinity，例如 varchar 與 text 實際上相同
+// 規則見 https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+fn affinity(declared_type: &str) -> &'static str {
+    let t = declared_type.to_ascii_uppercase();
+    if t.contains("INT") {
+        "INTEGER"
+    } else if t.contains("CHAR") || t.contains("CLOB") || t.contains("TEXT") {
+        "TEXT"
+    } else if t.is_empty() || t.contains("BLOB") {
+        "BLOB"
+    } else if t.contains("REAL") || t.contains("FLOA") || t.contains("DOUB") {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
+
+fn describe(column: &ColumnInfo) -> String {
+    format!(
+        "{} {}",
+        affinity(&column.declared_type),
+        if column.not_null { "NOT NULL" } else { "NULL" }
+    )
+}
+
+/// 檢查資料表是否與 Entity 定義一致，不一致時回傳列出所有差異的錯誤
+///
+/// 預期的結構取自在記憶體資料庫中以 `create_table_from_entity` 建出的資料表，
+/// 與 SeaORM 實際讀寫時使用的欄位完全相同。
+/// 資料表多出的欄位若可為空或有預設值，不影響 Entity 讀寫，只印出提示。
+pub async fn verify_entity<E: EntityTrait>(conn: &impl ConnectionTrait, entity: E) -> anyhow::Result<()> {
+    let table = entity.table_name().to_string();
+
+    let expected = {
+        let memory = Database::connect("sqlite::memory:").await?;
+        let backend = memory.get_database_backend();
+        let create = Schema::new(backend).create_table_from_entity(entity);
+        memory.execute(backend.build(&create)).await?;
+        table_columns(&memory, &table).await?
+    };
+    let actual = table_columns(conn, &table).await?;
+    if actual.is_empty() {
+        anyhow::bail!("Table `{}` does not exist, run `migrate up` first", table);
+    }
+
+    let mut problems = Vec::new();
+    for want in &expected {
+        let Some(found) = actual.iter().find(|c| c.name == want.name) else {
+            problems.push(format!("missing column `{}` ({})", want.name, describe(want)));
+            continue;
+        };
+        // SQLite 的 INTEGER PRIMARY KEY 不一定帶 NOT NULL 旗標，主鍵只比對型別
+        let nullability_differs = !want.primary_key && want.not_null != found.not_null;
+        if affinity(&want.declared_type) != affinity(&found.declared_type) || nullability_differs {
+            problems.push(format!(
+                "column `{}`: entity expects {}, table has {}",
+                want.name,
+                describe(want),
+                describe(found)
+            ));
+        }
+    }
+    for extra in actual.iter().filter(|c| !expected.iter().any(|e| e.name == c.name)) {
+        if extra.not_null && !extra.has_default && !extra.primary_key {
+            problems.push(format!(
+                "unexpected column `{}` ({}) without default, inserts through the entity would fail",
+                extra.name,
+                describe(extra)
+            ));
+        } else {
+            tracing::warn!("Column `{}.{}` is not mapped by the entity", table, extra.name);
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        anyhow::bail!(
+            "Schema of table `{}` does not match the entity definition:\n  - {}",
+            table,
+            problems.join("\n  - ")
+        )
+    }
+}
@@ -0,0 +1,121 @@
+//! 優雅關閉 (ex04 / ex06 / ex07 共用)
+//!
+//! 收到 SIGINT (Ctrl+C) 或 SIGTERM 後：
+//! 1. 進入 draining：`/readyz` 改回傳 503，讓負載平衡器停止導入新流量
+//! 2. 等待 `SHUTDOWN_DELAY` (預設 0 秒) 讓負載平衡器察覺，之後停止接受新連線
+//! 3. 等待處理中的請求完成，最多 `SHUTDOWN_TIMEOUT` (預設 30 秒)，逾時則直接中斷
+//!
+//! 伺服器結束後由各範例自行關閉資料庫連線。時間以秒為單位，例如 `SHUTDOWN_TIMEOUT=10`
+
+use std::future::IntoFuture;
+use std::time::Duration;
+use tokio::sync::watch;
+
+const DELAY_ENV: &str = "SHUTDOWN_DELAY";
+const TIMEOUT_ENV: &str = "SHUTDOWN_TIMEOUT";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 關閉流程的狀態，clone 後可放進 handler 的 state 中查詢是否正在關閉
+#[derive(Clone)]
+pub struct Shutdown {
+    draining: watch::Receiver<bool>,
+    delay: Duration,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    /// 讀取設定並開始監聽訊號，需在 tokio runtime 中呼叫
+    pub fn install() -> Result<Shutdown, String> {
+        let delay = duration_from_env(DELAY_ENV, Duration::ZERO)?;
+        let timeout = duration_from_env(TIMEOUT_ENV, DEFAULT_TIMEOUT)?;
+        let (sender, draining) = watch::channel(false);
+        tokio::spawn(async move {
+            let signal = signal().await;
+            tracing::info!(signal, "shutting down, no longer ready");
+            let _ = sender.send(true);
+        });
+        Ok(Shutdown {
+            draining,
+            delay,
+            timeout,
+        })
+    }
+
+    /// 是否已收到關閉訊號
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    // 等到收到關閉訊號
+    async fn draining(mut self) {
+        let _ = self.draining.wait_for(|draining| *draining).await;
+    }
+
+    /// 傳給 `with_graceful_shutdown`：收到訊號並等待 `SHUTDOWN_DELAY` 後停止接受新連線
+    pub async fn stop_accepting(self) {
+        let delay = self.delay;
+        self.draining().await;
+        tokio::time::sleep(delay).await;
+        tracing::info!("stopped accepting connections, draining in-flight requests");
+    }
+
+    /// 執行伺服器直到結束；收到訊號後最多再等 `SHUTDOWN_TIMEOUT`，逾時則中斷剩下的請求
+    pub async fn drain<F>(self, server: F) -> std::io::Result<()>
+    where
+        F: IntoFuture<Output = std::io::Result<()>>,
+    {
+        let (delay, timeout) = (self.delay, self.timeout);
+        let server = server.into_future();
+        tokio::pin!(server);
+        tokio::select! {
+            result = &mut server => return result,
+            _ = self.draining() => {}
+        }
+        match tokio::time::timeout(delay + timeout, server).await {
+            Ok(result) => {
+                tracing::info!("all in-flight requests finished");
+                result
+            }
+            Err(_) => {
+                tracing::warn!("drain timeout of {}s exceeded, aborting remaining requests", timeout.as_secs());
+                Ok(())
+            }
+        }
+    }
+}
+
+// 等待 SIGINT 或 SIGTERM，回傳訊號名稱
+async fn signal() -> &'static str {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => "SIGINT",
+        _ = terminate => "SIGTERM",
+    }
+}
+
+fn duration_from_env(name: &str, default: Duration) -> Result<Duration, String> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => value
+            .parse::<u64>()
+            .map(Duration::from_secs)
+            .map_err(|_| format!("Invalid {} `{}`, expected a number of seconds", name, value)),
+        _ => Ok(default),
+    }
+}
@@ -0,0 +1,56 @@
+//! 由文章標題產生 URL slug
+//!
+//! 標題先以 deunicode 轉寫成 ASCII (中文轉為拼音、日文假名轉為羅馬字)，
+//! 再轉小寫、非英數字元以 `-` 取代，例如 `學習 Rust!` → `xue-xi-rust`。
+//! 同名的 slug 以 `-2`、`-3` … 後綴區分。
+
+use deunicode::deunicode;
+
+/// slug 的最大長度 (不含衝突後綴)
+const MAX_LEN: usize = 80;
+
+/// 標題轉寫後沒有任何英數字元時使用的 slug
+const FALLBACK: &str = "post";
+
+/// 將標題轉為 slug 的基底 (尚未處理衝突)
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in deunicode(title).chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    // 過長時在最後一個 `-` 截斷，避免切在單字中間
+    if slug.len() > MAX_LEN {
+        slug.truncate(MAX_LEN);
+        if let Some(pos) = slug.rfind('-') {
+            slug.truncate(pos);
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        FALLBACK.to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// 第 n 個候選 slug：第一個是基底本身，之後為 `基底-2`、`基底-3` …
+pub fn candidate(base: &str, n: u32) -> String {
+    if n <= 1 {
+        base.to_string()
+    } else {
+        format!("{}-{}", base, n)
+    }
+}
+
+/// 判斷 slug 是否由此基底產生 (基底本身或帶數字後綴)
+pub fn has_base(slug: &str, base: &str) -> bool {
+    slug == base
+        || slug
+            .strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 文章標籤，名稱唯一 (一律存成小寫)
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[schema(as = Tag)]
+#[sea_orm(table_name = "tags")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub name: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+/// 標籤與文章為多對多，經由 post_tags 關聯表
+impl Related<super::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::post_tag_entity::Relation::Post.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::post_tag_entity::Relation::Tag.def().rev())
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
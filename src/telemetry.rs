@@ -0,0 +1,131 @@
+//! 結構化日誌與請求追蹤 (ex04 / ex06 / ex07 共用)
+//!
+//! - 每個請求都有 request id：沿用 client 帶來的 `X-Request-Id`，沒有時自動產生，並放回回應 header
+//! - 每個請求一個 `request` span，記錄 method、路由樣板、狀態碼與耗時，
+//!   請求期間的所有日誌 (包含 SQL) 都會帶上這些欄位
+//! - SQL 以 `db.query` 事件記錄語句與耗時，層級為 debug
+//!
+//! 輸出格式以 `LOG_FORMAT` 選擇：`pretty` (預設，人類閱讀) 或 `json` (一行一筆，給日誌系統收集)；
+//! 層級以 `RUST_LOG` 設定，預設 `info`，例如 `RUST_LOG=debug` 可看到每一條 SQL
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use rand_core::{OsRng, RngCore};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+const FORMAT_ENV: &str = "LOG_FORMAT";
+/// 請求追蹤用的 header
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// client 帶來的 request id 超過此長度時不採用，改為自動產生
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 日誌輸出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    /// 讀取 `LOG_FORMAT`，未設定時為 pretty
+    pub fn from_env() -> Result<LogFormat, String> {
+        match std::env::var(FORMAT_ENV).as_deref() {
+            Err(_) | Ok("") | Ok("pretty") => Ok(LogFormat::Pretty),
+            Ok("json") => Ok(LogFormat::Json),
+            Ok(other) => Err(format!("Invalid {} `{}`, expected pretty or json", FORMAT_ENV, other)),
+        }
+    }
+}
+
+/// 安裝全域的 tracing subscriber，需在啟動時最先呼叫
+pub fn init() -> Result<(), String> {
+    let format = LogFormat::from_env()?;
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(filter);
+    let result = match format {
+        LogFormat::Pretty => registry.with(tracing_subscriber::fmt::layer()).try_init(),
+        // 每行一個 JSON 物件：{"timestamp", "level", "fields", "target", "span", "spans"}
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().json()).try_init(),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// 請求追蹤 middleware
+/// 以 `layer` 套用在最外層，限流等 middleware 擋下的請求也會被記錄；
+/// 沒有對應路由的請求 (404) 路由樣板記為 `unmatched`；
+/// 自動產生的 request id 也會寫回 request header，handler 一律從 header 讀取即可
+pub async fn trace_request(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map_or_else(new_request_id, str::to_string);
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        route,
+        path = request.uri().path(),
+        status = Empty,
+        latency_ms = Empty,
+    );
+
+    let header_value = HeaderValue::from_str(&request_id).ok();
+    if let Some(value) = &header_value {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    }
+
+    let start = Instant::now();
+    let mut response = next.run(request).instrument(span.clone()).await;
+    let status = response.status();
+    span.record("status", status.as_u16());
+    span.record("latency_ms", millis(start.elapsed()));
+    span.in_scope(|| {
+        if status.is_server_error() {
+            tracing::error!("request failed");
+        } else {
+            tracing::info!("request completed");
+        }
+    });
+
+    if let Some(value) = header_value {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// 記錄一條 SQL 的執行結果，在目前的 span (通常是 request) 之下
+#[allow(dead_code)] // ex04 沒有資料庫
+pub fn record_query(statement: &str, elapsed: Duration, failed: bool) {
+    if failed {
+        tracing::warn!(target: "db", statement, elapsed_ms = millis(elapsed), "db.query failed");
+    } else {
+        tracing::debug!(target: "db", statement, elapsed_ms = millis(elapsed), "db.query");
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// 只接受可見的 ASCII 字元，避免換行等字元混入日誌
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn new_request_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
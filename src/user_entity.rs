@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::policy::Role;
+
+/// 文章作者，對應 posts.db 中的 users 表
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize, ToSchema)]
+#[schema(as = User)]
+#[sea_orm(table_name = "users")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub username: String,
+    #[sea_orm(unique)]
+    pub email: String,
+    pub role: Role,
+    /// 樂觀鎖版本號，每次修改角色 + 1，並作為 ETag 使用
+    #[sea_orm(default_value = 1)]
+    pub version: i32,
+    /// argon2 雜湊，不會出現在 JSON 回應中；沒有密碼的使用者無法登入
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+}
+
+/// 一位使用者可以有多篇文章 (posts.author_id -> users.id)
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::entity::Entity")]
+    Posts,
+}
+
+impl Related<super::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Posts.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}